@@ -0,0 +1,4041 @@
+// Conversion, playback and export for turning videos into terminal text, the binary in main.rs
+// is a thin command line wrapper over this
+use anyhow::{Context, Result}; // Automatically handle the error types
+use terminal_size::{terminal_size, Width, Height};
+
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{stdout, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use clap::ValueEnum;
+use rayon::prelude::*;
+use rodio::source::{Buffered, Speed};
+use rodio::{Decoder as AudioDecoder, OutputStream, Sink, Source};
+use unicode_width::UnicodeWidthChar;
+use wide::f64x4;
+
+use crossterm::{
+    execute, queue,
+    style::{Print},
+    cursor::{Show, Hide, MoveTo},
+    event::{poll, read, Event, KeyCode, KeyModifiers},
+    terminal::{enable_raw_mode, disable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use opencv::{
+    prelude::*,
+    videoio,
+    core::{add_weighted, flip, lut, rotate, Point, Rect, Scalar, Size, Vector, Vec3b, BORDER_DEFAULT, CV_32F, CV_8U, CV_8UC3, ROTATE_180, ROTATE_90_CLOCKWISE, ROTATE_90_COUNTERCLOCKWISE},
+    imgcodecs::{imcount, imread, imreadmulti, IMREAD_COLOR},
+    imgproc::{cvt_color, filter_2d, gaussian_blur, resize, sobel, COLOR_BGR2GRAY, COLOR_BGR2HSV, COLOR_HSV2BGR, INTER_AREA, INTER_CUBIC, INTER_LINEAR, INTER_NEAREST}, // optflow::ST_BILINEAR
+};
+
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "bmp", "webp", "tif", "tiff", "ppm"];
+pub const DEFAULT_FPS: f64 = 30.0;
+
+const ANIMATION_EXTENSIONS: [&str; 2] = ["gif", "webp"];
+pub const ANIMATION_FPS: f64 = 100.0; // gif delays are in hundredths of a second, timed animations play on that tick
+const GIF_MIN_DELAY: u64 = 20; // ms
+const GIF_DEFAULT_DELAY: u64 = 100; // ms, what browsers play faster gifs at
+const ANTIALIAS_SAMPLES: i32 = 4; // samples per side of a cell with --aa, 16 per cell
+const PARALLEL_DECODE_MIN_FRAMES: usize = 64; // shorter videos aren't worth opening a capture per thread
+const RESET_COLOR: &str = "\x1b[0m";
+const LABEL_BACKGROUND: &str = "\x1b[40m"; // black behind subtitles and the countdown
+
+const EDGE_THRESHOLD: f32 = 100.0; // sobel magnitude below this is treated as flat
+
+const GIF_FONT_SIZE: f32 = 16.0;
+const GIF_FONT_PATHS: [&str; 4] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/System/Library/Fonts/Menlo.ttc",
+    "C:\\Windows\\Fonts\\consola.ttf",
+];
+
+// bit for each dot of a braille character, indexed [row][column]
+const BRAILLE_DOTS: [[u32; 2]; 4] = [
+    [0x01, 0x08],
+    [0x02, 0x10],
+    [0x04, 0x20],
+    [0x40, 0x80],
+];
+
+const ANSI256_CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+const ANSI16_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+// dark -> bright, --levels picks evenly spaced symbols from the blocks, or the longer ascii ramp past 5 levels
+const BLOCK_RAMP: [&str; 5] = ["█", "▓", "▒", "░", " "];
+const ASCII_RAMP: &str = "$@B%8&WM#*oahkbdpqwmZO0QLCJUYXzcvunxrjft/\\|()1{}[]?-_+~<>i!lI;:,\"^`'. ";
+
+// dark -> bright for --mode boxfill, eight fill levels in a cell plus empty
+const BOXFILL_RAMP: [&str; 9] = ["█", "▇", "▆", "▅", "▄", "▃", "▂", "▁", " "];
+
+const KEY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// The videoio API constant captures take
+fn backend_api(backend: Backend) -> i32 {
+    match backend {
+        Backend::Any => videoio::CAP_ANY,
+        Backend::Ffmpeg => videoio::CAP_FFMPEG,
+        Backend::Gstreamer => videoio::CAP_GSTREAMER,
+        Backend::Msmf => videoio::CAP_MSMF,
+        Backend::Dshow => videoio::CAP_DSHOW,
+        Backend::V4l2 => videoio::CAP_V4L2,
+        Backend::Avfoundation => videoio::CAP_AVFOUNDATION,
+    }
+}
+
+// A forced backend that can't open something fails the same way a bad file does, so the message says which one it was
+fn backend_hint(backend: Backend) -> String {
+    match backend {
+        Backend::Any => String::new(),
+        backend => format!(" with the {} backend", backend.to_possible_value().expect("No backend is skipped").get_name()),
+    }
+}
+
+// Backend::Any lets OpenCV pick, the others force one for machines where its pick reads garbage
+pub fn open_device(device: i32, backend: Backend) -> Result<videoio::VideoCapture, opencv::Error> {
+    let video_capture = videoio::VideoCapture::new(device, backend_api(backend))?;
+
+    if !video_capture.is_opened()? {
+        return Err(opencv::Error::new(opencv::core::StsError, format!("Failed to open capture device{}", backend_hint(backend))));
+    }
+
+    Ok(video_capture)
+}
+
+pub fn open_video(path: &str, backend: Backend) -> Result<videoio::VideoCapture, opencv::Error> {
+    let mut video_capture = videoio::VideoCapture::from_file(path, backend_api(backend))?;
+
+    // phone videos are stored sideways with a rotation tag, backends that read it hand back upright frames.
+    // The ones that can't just say no, so the result doesn't matter
+    let _ = video_capture.set(videoio::CAP_PROP_ORIENTATION_AUTO, 1.0);
+
+    if !video_capture.is_opened()? {
+        return Err(opencv::Error::new(
+            opencv::core::StsError,
+            format!("{} exists but couldn't be decoded as a video{}", path, backend_hint(backend)),
+        ));
+    } 
+
+    Ok(video_capture)
+}
+
+// What ffprobe reports about a file's first video stream, fps and duration are 0 when it doesn't say
+pub struct FfmpegStream {
+    pub size: Size,
+    pub fps: f64,
+    pub duration: f64,
+    pub codec: String,
+}
+
+pub fn ffprobe(path: &str) -> Result<FfmpegStream, opencv::Error> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=width,height,avg_frame_rate,codec_name:format=duration"])
+        .args(["-of", "default=noprint_wrappers=1"])
+        .arg(path)
+        .output()
+        .map_err(|e| ffmpeg_error("ffprobe", e))?;
+
+    let mut stream = FfmpegStream { size: Size::new(0, 0), fps: 0.0, duration: 0.0, codec: String::new() };
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let (key, value) = match line.split_once('=') {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        match key {
+            "width" => stream.size.width = value.parse().unwrap_or(0),
+            "height" => stream.size.height = value.parse().unwrap_or(0),
+            // a fraction like 30000/1001, 0/0 when it's unknown
+            "avg_frame_rate" => if let Some((numerator, denominator)) = value.split_once('/') {
+                let (numerator, denominator): (f64, f64) = (numerator.parse().unwrap_or(0.0), denominator.parse().unwrap_or(0.0));
+                stream.fps = if denominator > 0.0 { numerator / denominator } else { 0.0 };
+            },
+            "duration" => stream.duration = value.parse().unwrap_or(0.0),
+            "codec_name" => stream.codec = value.to_string(),
+            _ => {}
+        }
+    }
+
+    if stream.size.width <= 0 || stream.size.height <= 0 {
+        return Err(opencv::Error::new(
+            opencv::core::StsError,
+            format!("ffprobe found no video stream in {}", path),
+        ));
+    }
+
+    Ok(stream)
+}
+
+fn ffmpeg_error(program: &str, e: std::io::Error) -> opencv::Error {
+    let message = if e.kind() == std::io::ErrorKind::NotFound {
+        format!("{} isn't installed or isn't on the PATH, it's needed for --decoder ffmpeg", program)
+    } else {
+        format!("Couldn't run {}: {}", program, e)
+    };
+
+    opencv::Error::new(opencv::core::StsError, message)
+}
+
+// Raw BGR frames piped out of an ffmpeg process, for files the OpenCV build can't decode. ffmpeg does the
+// trimming itself and its rotation handling is off so frames match the size ffprobe reports (--rotate still works).
+// The process is killed when the reader is dropped, so stopping early doesn't leave it running
+pub struct FfmpegReader {
+    child: Child,
+    stdout: ChildStdout,
+    size: Size,
+    frame_count: usize, // 0 when ffprobe didn't know the frame rate or duration
+}
+
+impl FfmpegReader {
+    pub fn open(path: &str, trim: Trim) -> Result<FfmpegReader, opencv::Error> {
+        let stream = ffprobe(path)?;
+
+        let start = trim.start.unwrap_or(0.0);
+        let end = trim.end.unwrap_or(stream.duration);
+
+        let mut command = Command::new("ffmpeg");
+        command.args(["-v", "error", "-nostdin", "-noautorotate"]);
+
+        if start > 0.0 {
+            command.arg("-ss").arg(start.to_string());
+        }
+
+        command.arg("-i").arg(path);
+
+        if trim.end.is_some() {
+            command.arg("-t").arg((end - start).max(0.0).to_string());
+        }
+
+        if let Some(max_frames) = trim.max_frames {
+            command.arg("-frames:v").arg(max_frames.to_string());
+        }
+
+        // errors would land on top of the animation, an empty read is reported instead
+        let mut child = command
+            .args(["-an", "-f", "rawvideo", "-pix_fmt", "bgr24", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| ffmpeg_error("ffmpeg", e))?;
+
+        let stdout = child.stdout.take().expect("ffmpeg's stdout is piped");
+
+        let mut frame_count = if stream.fps > 0.0 && end > start { ((end - start) * stream.fps).round() as usize } else { 0 };
+        if let Some(max_frames) = trim.max_frames {
+            if frame_count == 0 || frame_count > max_frames {
+                frame_count = max_frames;
+            }
+        }
+
+        Ok(FfmpegReader { child, stdout, size: stream.size, frame_count })
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    // Like VideoCapture::read, false once ffmpeg runs out. A cut off last frame counts as the end
+    pub fn read(&mut self, frame: &mut Mat) -> Result<bool, opencv::Error> {
+        if frame.size()? != self.size || frame.typ() != CV_8UC3 {
+            *frame = Mat::new_rows_cols_with_default(self.size.height, self.size.width, CV_8UC3, Scalar::all(0.0))?;
+        }
+
+        match self.stdout.read_exact(frame.data_bytes_mut()?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(opencv::Error::new(opencv::core::StsError, format!("Couldn't read from ffmpeg: {}", e))),
+        }
+    }
+}
+
+impl Drop for FfmpegReader {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// The whole trim through ffmpeg, for VideoToAscii::load with Decoder::Ffmpeg
+fn get_ffmpeg_frames(path: &str, trim: Trim) -> Result<Vec<Mat>, opencv::Error> {
+    let mut reader = FfmpegReader::open(path, trim)?;
+    let mut frames = Vec::new();
+    let mut frame = Mat::default();
+
+    while reader.read(&mut frame)? {
+        frames.push(std::mem::take(&mut frame));
+    }
+
+    Ok(frames)
+}
+
+// What --info prints about a video, the numbers are 0 when the container doesn't say
+pub struct VideoInfo {
+    pub size: Size,
+    pub fps: f64,
+    pub frame_count: usize,
+    pub duration: f64, // seconds
+    pub codec: String,
+    pub decoder: String, // what opened it, the OpenCV backend's name or ffmpeg
+}
+
+pub fn video_info(path: &str, decoder: Decoder, backend: Backend) -> Result<VideoInfo, opencv::Error> {
+    if is_animation(path) {
+        let frame_count = imcount(path, IMREAD_COLOR)? as usize;
+        let duration = animation_delays(path).map_or(0.0, |delays| delays.iter().sum::<u64>() as f64 / 1000.0);
+        let extension = Path::new(path).extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+
+        return Ok(VideoInfo {
+            size: load_image(path)?.size()?,
+            fps: if duration > 0.0 { frame_count as f64 / duration } else { 0.0 },
+            frame_count,
+            duration,
+            codec: extension.to_lowercase(),
+            decoder: String::from("imreadmulti"),
+        });
+    }
+
+    if decoder == Decoder::Ffmpeg {
+        let stream = ffprobe(path)?;
+
+        return Ok(VideoInfo {
+            size: stream.size,
+            fps: stream.fps,
+            frame_count: (stream.duration * stream.fps).round() as usize,
+            duration: stream.duration,
+            codec: stream.codec,
+            decoder: String::from("ffmpeg"),
+        });
+    }
+
+    let mut video_capture = open_video(path, backend)?;
+
+    let fps = video_capture.get(videoio::CAP_PROP_FPS)?.max(0.0);
+    let frame_count = video_capture.get(videoio::CAP_PROP_FRAME_COUNT)?.max(0.0);
+    let fourcc = video_capture.get(videoio::CAP_PROP_FOURCC)? as i32;
+
+    // the reported size can be 0 on streams that only know it after the first frame
+    let mut size = Size::new(
+        video_capture.get(videoio::CAP_PROP_FRAME_WIDTH)? as i32,
+        video_capture.get(videoio::CAP_PROP_FRAME_HEIGHT)? as i32,
+    );
+    if size.width <= 0 || size.height <= 0 {
+        size = first_frame_size(&mut video_capture)?;
+    }
+
+    Ok(VideoInfo {
+        size,
+        fps,
+        frame_count: frame_count as usize,
+        duration: if fps > 0.0 { frame_count / fps } else { 0.0 },
+        codec: fourcc_to_string(fourcc),
+        decoder: video_capture.get_backend_name()?,
+    })
+}
+
+// CAP_PROP_FOURCC packs the codec's four letter code into an int, first letter in the lowest byte
+fn fourcc_to_string(fourcc: i32) -> String {
+    let code: String = fourcc.to_le_bytes().iter()
+        .map(|&byte| byte as char)
+        .filter(|ch| ch.is_ascii_graphic() || *ch == ' ')
+        .collect();
+
+    match code.trim() {
+        "" => String::from("unknown"),
+        code => code.to_string(),
+    }
+}
+
+// Checks the trim against the video's duration and seeks to its start, returns how many frames it covers
+fn seek_to_trim(video_capture: &mut videoio::VideoCapture, trim: Trim) -> Result<f64, opencv::Error> {
+    let frame_count = video_capture.get(videoio::CAP_PROP_FRAME_COUNT)?.max(0.0);
+    let fps = video_capture.get(videoio::CAP_PROP_FPS)?;
+
+    let start = trim.start.unwrap_or(0.0);
+    let duration = if fps > 0.0 && frame_count > 0.0 { frame_count / fps } else { f64::INFINITY };
+    let end = trim.end.unwrap_or(duration);
+
+    if start < 0.0 || start >= duration {
+        return Err(opencv::Error::new(
+            opencv::core::StsOutOfRange,
+            format!("Start {}s is outside the video (0s - {:.2}s)", start, duration),
+        ));
+    }
+
+    if end <= start || (trim.end.is_some() && end > duration) {
+        return Err(opencv::Error::new(
+            opencv::core::StsOutOfRange,
+            format!("End {}s must be after the start {}s and within the video ({:.2}s)", end, start, duration),
+        ));
+    }
+
+    if start > 0.0 {
+        video_capture.set(videoio::CAP_PROP_POS_MSEC, start * 1000.0)?;
+    }
+
+    let frame_count = if duration.is_finite() { ((end - start) * fps).round() } else { frame_count };
+
+    match trim.max_frames {
+        Some(max_frames) if frame_count == 0.0 || frame_count > max_frames as f64 => Ok(max_frames as f64),
+        _ => Ok(frame_count),
+    }
+}
+
+pub fn first_frame_size(video_capture: &mut videoio::VideoCapture) -> Result<Size, opencv::Error> {
+    let mut frame = Mat::default();
+
+    if !video_capture.read(&mut frame)? {
+        return Err(opencv::Error::new(opencv::core::StsError, "Failed to read a frame"));
+    }
+
+    frame.size()
+}
+
+// Size that keeps the source's shape for size_x by size_y, char_aspect is how many times taller a cell is than wide.
+// Contain stays inside both, width and height fill that side and let the other one spill over
+pub fn fit_size(source_size: Size, size_x: i32, size_y: i32, char_aspect: f64, fit: Fit) -> (i32, i32) {
+    if source_size.width <= 0 || source_size.height <= 0 {
+        return (size_x, size_y);
+    }
+
+    // output columns per output row
+    let ratio = source_size.width as f64 / source_size.height as f64 * char_aspect;
+
+    let mut fit_x = size_x;
+    let mut fit_y = (fit_x as f64 / ratio) as i32;
+
+    // Check if the calculated size exceeds the available height
+    if fit == Fit::Height || (fit == Fit::Contain && fit_y > size_y) {
+        fit_y = size_y;
+        fit_x = (fit_y as f64 * ratio) as i32;
+    }
+
+    (fit_x.max(1), fit_y.max(1))
+}
+
+pub fn native_fps(path: &str, backend: Backend) -> Result<f64, opencv::Error> {
+    let video_capture = open_video(path, backend)?;
+    let fps = video_capture.get(videoio::CAP_PROP_FPS)?;
+
+    // some containers don't report a frame rate
+    if fps.is_nan() || fps <= 0.0 {
+        return Ok(DEFAULT_FPS);
+    }
+
+    Ok(fps)
+}
+
+// Reads the next frame inside the trim, false once it's over. read is how many frames came before it
+fn read_trimmed_frame(
+    video_capture: &mut videoio::VideoCapture,
+    trim: Trim,
+    read: usize,
+    frame: &mut Mat,
+) -> Result<bool, opencv::Error> {
+    if trim.max_frames.map_or(false, |max_frames| read >= max_frames) {
+        return Ok(false);
+    }
+
+    Ok(video_capture.read(frame)? && !past_trim_end(video_capture, trim)?)
+}
+
+// Once read_trimmed_frame returns false, whether it was --max-frames that stopped it with more of the trim left
+fn cut_by_max_frames(video_capture: &mut videoio::VideoCapture, trim: Trim, read: usize) -> Result<bool, opencv::Error> {
+    Ok(trim.max_frames == Some(read) && video_capture.grab()? && !past_trim_end(video_capture, trim)?)
+}
+
+fn past_trim_end(video_capture: &videoio::VideoCapture, trim: Trim) -> Result<bool, opencv::Error> {
+    match trim.end {
+        Some(end) => Ok(video_capture.get(videoio::CAP_PROP_POS_MSEC)? > end * 1000.0),
+        None => Ok(false),
+    }
+}
+
+pub fn is_image_path(path: &str) -> bool {
+    match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+pub fn load_image(path: &str) -> Result<Mat, opencv::Error> {
+    let image = imread(path, IMREAD_COLOR)?;
+
+    // imread doesn't error on unreadable files, it hands back an empty mat
+    if image.rows() == 0 {
+        return Err(opencv::Error::new(
+            opencv::core::StsError,
+            format!("{} exists but couldn't be decoded as an image", path),
+        ));
+    }
+
+    Ok(image)
+}
+
+// Grabs a single frame, the middle one unless an index is given
+pub fn get_preview_frame(path: &str, index: Option<usize>, backend: Backend) -> Result<Mat, opencv::Error> {
+    let mut video_capture = open_video(path, backend)?;
+
+    let frame_count = video_capture.get(videoio::CAP_PROP_FRAME_COUNT)?.max(0.0) as usize;
+    let index = index.unwrap_or(frame_count / 2);
+
+    if index > 0 {
+        video_capture.set(videoio::CAP_PROP_POS_FRAMES, index as f64)?;
+    }
+
+    let mut frame = Mat::default();
+
+    if !video_capture.read(&mut frame)? {
+        return Err(opencv::Error::new(
+            opencv::core::StsOutOfRange,
+            format!("Couldn't read frame {}, the video has {} frames", index, frame_count),
+        ));
+    }
+
+    Ok(frame)
+}
+
+// Gifs and webps with more than one frame, VideoCapture often reads only the first one of these
+pub fn is_animation(path: &str) -> bool {
+    let animated_extension = match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => ANIMATION_EXTENSIONS.contains(&extension.to_lowercase().as_str()),
+        None => false,
+    };
+
+    animated_extension && imcount(path, IMREAD_COLOR).map_or(false, |count| count > 1)
+}
+
+// Each frame's delay in ms from a gif's own timing, None for webps and anything the gif decoder can't read
+pub fn animation_delays(path: &str) -> Option<Vec<u64>> {
+    let mut decode_options = gif::DecodeOptions::new();
+    decode_options.set_color_output(gif::ColorOutput::Indexed);
+
+    let mut decoder = decode_options.read_info(File::open(path).ok()?).ok()?;
+    let mut delays = Vec::new();
+
+    while let Some(frame) = decoder.next_frame_info().ok()? {
+        let delay = frame.delay as u64 * 10; // stored in hundredths of a second
+
+        // browsers slow down gifs that ask to be this fast, most were made expecting that
+        delays.push(if delay < GIF_MIN_DELAY { GIF_DEFAULT_DELAY } else { delay });
+    }
+
+    Some(delays)
+}
+
+// Every frame of an animation through imreadmulti with how many ticks it's held for. With gif timing that's as
+// many ANIMATION_FPS ticks as its delay, without it every frame is one tick at --fps. Each frame is kept once,
+// expand_holds repeats its text after conversion. The bool is whether --max-frames cut the trim short
+fn get_animation_frames(path: &str, trim: Trim) -> Result<(Vec<(Mat, usize)>, bool), opencv::Error> {
+    let mut decoded = Vector::<Mat>::new();
+
+    if !imreadmulti(path, &mut decoded, IMREAD_COLOR)? || decoded.is_empty() {
+        return Err(opencv::Error::new(
+            opencv::core::StsError,
+            format!("{} exists but couldn't be decoded as an animation", path),
+        ));
+    }
+
+    let delays = animation_delays(path).filter(|delays| delays.len() == decoded.len());
+
+    let mut holds = Vec::with_capacity(decoded.len());
+    let mut i = 0;
+    while i < decoded.len() {
+        holds.push(match &delays {
+            Some(delays) => ((delays[i] as f64 * ANIMATION_FPS / 1000.0).round() as usize).max(1),
+            None => 1,
+        });
+        i += 1;
+    }
+
+    let ticks: usize = holds.iter().sum();
+
+    // --start and --end in the animation's own time, untimed ones count at the default rate
+    let fps = if delays.is_some() { ANIMATION_FPS } else { DEFAULT_FPS };
+    let start = (trim.start.unwrap_or(0.0) * fps) as usize;
+    let mut end = trim.end.map_or(ticks, |end| ((end * fps) as usize).min(ticks));
+
+    if start >= end {
+        return Err(opencv::Error::new(
+            opencv::core::StsOutOfRange,
+            format!("The trim is outside the animation (0s - {:.2}s)", ticks as f64 / fps),
+        ));
+    }
+
+    let mut truncated = false;
+
+    if let Some(max_frames) = trim.max_frames {
+        if end - start > max_frames {
+            end = start + max_frames;
+            truncated = true;
+        }
+    }
+
+    // the part of each frame's hold that falls inside start..end
+    let mut frames = Vec::new();
+    let mut tick = 0;
+    let mut i = 0;
+    while i < decoded.len() && tick < end {
+        let held = (tick + holds[i]).min(end).saturating_sub(tick.max(start));
+
+        if held > 0 {
+            frames.push((decoded.get(i)?, held));
+        }
+
+        tick += holds[i];
+        i += 1;
+    }
+
+    Ok((frames, truncated))
+}
+
+// Repeats each animation frame's text for the ticks it's held, skip_every drops ticks the same way it drops a
+// video's frames. Repeats are clones of one converted text, playback and the exports draw a run of them once
+fn expand_holds(frames_text: Vec<String>, holds: &[usize], skip_every: usize) -> Vec<String> {
+    let mut expanded = Vec::new();
+    let mut tick = 0;
+
+    for (frame_text, &hold) in frames_text.into_iter().zip(holds) {
+        let kept = kept_ticks(tick, hold, skip_every);
+        expanded.extend(std::iter::repeat(frame_text).take(kept));
+        tick += hold;
+    }
+
+    expanded
+}
+
+// How many of the hold ticks from tick on skip_every keeps
+fn kept_ticks(tick: usize, hold: usize, skip_every: usize) -> usize {
+    (tick..tick + hold).filter(|&tick| keep_frame(tick, skip_every)).count()
+}
+
+// The trimmed frames and whether --max-frames cut the trim short, animations go through get_animation_frames
+pub fn get_video_frames(path: &str, trim: Trim, backend: Backend) -> Result<(Vec<Mat>, bool), opencv::Error> {
+    let mut video_capture = open_video(path, backend)?;
+    let frame_count = seek_to_trim(&mut video_capture, trim)?;
+
+    if let Some(frames) = decode_ranges(path, backend, &video_capture, trim, frame_count)? {
+        return Ok(frames);
+    }
+
+    let mut frames = Vec::new();
+    let mut frame = Mat::default();
+
+    while read_trimmed_frame(&mut video_capture, trim, frames.len(), &mut frame)? {
+        frames.push(frame.clone());
+    }
+
+    let truncated = cut_by_max_frames(&mut video_capture, trim, frames.len())?;
+
+    Ok((frames, truncated))
+}
+
+// Splits the trim into a range of frames per thread, each decoded from its own capture and joined in order.
+// None when the video can't be split, ex an unknown length or a codec whose seeks don't land on the asked frame
+fn decode_ranges(
+    path: &str,
+    backend: Backend,
+    video_capture: &videoio::VideoCapture,
+    trim: Trim,
+    frame_count: f64,
+) -> Result<Option<(Vec<Mat>, bool)>, opencv::Error> {
+    let total = video_capture.get(videoio::CAP_PROP_FRAME_COUNT)?.max(0.0) as usize;
+    let first = video_capture.get(videoio::CAP_PROP_POS_FRAMES)?.max(0.0) as usize;
+
+    let count = (frame_count as usize).min(total.saturating_sub(first));
+    let threads = rayon::current_num_threads();
+
+    if count < PARALLEL_DECODE_MIN_FRAMES || threads < 2 {
+        return Ok(None);
+    }
+
+    let range_len = (count + threads - 1) / threads;
+    let mut ranges = Vec::new();
+
+    let mut start = first;
+    while start < first + count {
+        let end = (start + range_len).min(first + count);
+        ranges.push((start, end));
+        start = end;
+    }
+
+    let decoded = ranges
+        .par_iter()
+        .map(|&(start, end)| decode_range(path, backend, start, end))
+        .collect::<Result<Vec<_>, opencv::Error>>()?;
+
+    let mut frames = Vec::with_capacity(count);
+    for range in decoded {
+        match range {
+            Some(range) => frames.extend(range),
+            None => return Ok(None), // decode everything sequentially instead
+        }
+    }
+
+    let truncated = trim.max_frames == Some(count) && first + count < total;
+
+    Ok(Some((frames, truncated)))
+}
+
+fn decode_range(path: &str, backend: Backend, start: usize, end: usize) -> Result<Option<Vec<Mat>>, opencv::Error> {
+    let mut video_capture = open_video(path, backend)?;
+
+    if start > 0 {
+        video_capture.set(videoio::CAP_PROP_POS_FRAMES, start as f64)?;
+    }
+
+    // some codecs only seek to the nearest keyframe, the frames would be off
+    if video_capture.get(videoio::CAP_PROP_POS_FRAMES)?.round() as usize != start {
+        return Ok(None);
+    }
+
+    let mut frames = Vec::with_capacity(end - start);
+    let mut frame = Mat::default();
+
+    while frames.len() < end - start {
+        if !video_capture.read(&mut frame)? {
+            return Ok(None); // the container's frame count was off
+        }
+        frames.push(frame.clone());
+    }
+
+    Ok(Some(frames))
+}
+
+// Decodes, resizes and converts one frame at a time so only the text is kept in memory.
+// progress gets (frames done, total) after every frame, total is 0 when the video doesn't say.
+// The bool is whether --max-frames cut the trim short
+pub fn stream_frames_text<F: FnMut(usize, usize)>(converter: &VideoToAscii, progress: F) -> Result<(Vec<String>, bool), opencv::Error> {
+    let mut frames_text = Vec::new();
+
+    let truncated = for_each_frame_text(converter, progress, |frame_text| {
+        frames_text.push(frame_text);
+        true
+    })?;
+
+    Ok((frames_text, truncated))
+}
+
+// The loop behind stream_frames_text and VideoToAscii::spawn, each frame's text goes to emit as soon as
+// it's converted. Returning false from emit stops decoding early. Returns whether --max-frames cut the trim short
+fn for_each_frame_text<F: FnMut(usize, usize), E: FnMut(String) -> bool>(
+    converter: &VideoToAscii,
+    mut progress: F,
+    mut emit: E,
+) -> Result<bool, opencv::Error> {
+    let (path, trim, skip_every) = (converter.path.as_str(), converter.trim, converter.skip_every);
+    let (size_x, size_y, options) = (converter.size_x, converter.size_y, &converter.options);
+    let mut smoother = FrameSmoother::new(options.smooth);
+
+    // animations are small and come out of imreadmulti all at once anyway
+    if is_animation(path) {
+        let (frames, truncated) = get_animation_frames(path, trim)?;
+        progress(0, frames.len());
+
+        // each frame is converted once however long it's held, and sent again for every tick that's kept
+        let mut tick = 0;
+        let mut i = 0;
+        while i < frames.len() {
+            let (frame, hold) = (&frames[i].0, frames[i].1);
+            let kept = kept_ticks(tick, hold, skip_every);
+
+            if kept > 0 {
+                let resized_frame = smoother.smooth(resize_frame(frame, size_x, size_y, options)?)?;
+                let frame_text = frame_to_text(&resized_frame, size_x, size_y, options)?;
+
+                let mut k = 0;
+                while k < kept {
+                    if !emit(frame_text.clone()) {
+                        return Ok(false);
+                    }
+                    k += 1;
+                }
+            }
+
+            tick += hold;
+            i += 1;
+            progress(i, frames.len());
+        }
+
+        return Ok(truncated);
+    }
+
+    let mut frame = Mat::default();
+
+    if converter.decoder == Decoder::Ffmpeg {
+        let mut reader = FfmpegReader::open(path, trim)?;
+        progress(0, reader.frame_count());
+
+        let mut i = 0;
+        while reader.read(&mut frame)? {
+            if keep_frame(i, skip_every) {
+                let resized_frame = smoother.smooth(resize_frame(&frame, size_x, size_y, options)?)?;
+
+                if !emit(frame_to_text(&resized_frame, size_x, size_y, options)?) {
+                    return Ok(false);
+                }
+            }
+
+            i += 1;
+            progress(i, reader.frame_count());
+        }
+
+        return Ok(false); // ffmpeg stops at -frames:v itself
+    }
+
+    let mut video_capture = open_video(path, converter.backend)?;
+
+    let frame_count = seek_to_trim(&mut video_capture, trim)? as usize;
+    progress(0, frame_count);
+
+    let mut i = 0;
+    while read_trimmed_frame(&mut video_capture, trim, i, &mut frame)? {
+        if keep_frame(i, skip_every) {
+            let resized_frame = smoother.smooth(resize_frame(&frame, size_x, size_y, options)?)?;
+
+            if !emit(frame_to_text(&resized_frame, size_x, size_y, options)?) {
+                return Ok(false);
+            }
+        }
+
+        i += 1;
+        progress(i, frame_count);
+    }
+
+    cut_by_max_frames(&mut video_capture, trim, i)
+}
+
+// Keeps every skip_every-th frame starting with the first, 0 and 1 keep them all
+fn keep_frame(index: usize, skip_every: usize) -> bool {
+    skip_every == 0 || index % skip_every == 0
+}
+
+pub fn skip_frames(frames: &Vec<Mat>, skip_every: usize) -> Result<Vec<Mat>, opencv::Error> {
+    if skip_every == 0 {
+        return Ok(frames.clone()); // if skip_every is 0, skip no frames
+    }
+
+    let mut new_frames = Vec::new();
+
+    let mut i = 0;
+    while i < frames.len() {
+        if keep_frame(i, skip_every) {
+            new_frames.push(frames[i].clone());
+        }
+        i += 1;
+    }
+
+    Ok(new_frames)
+}
+
+// size_x and size_y are in cells, modes that pack several pixels into a cell get resized bigger
+pub fn resize_frame(frame: &Mat, size_x: i32, size_y: i32, options: &RenderOptions) -> Result<Mat, opencv::Error> {
+    let cropped_frame;
+    let frame = match options.crop {
+        Some(crop) => {
+            cropped_frame = crop_frame(frame, crop)?;
+            &cropped_frame
+        }
+        None => frame,
+    };
+
+    let rotated_frame;
+    let frame = match options.rotation {
+        90 | 180 | 270 => {
+            let code = match options.rotation {
+                90 => ROTATE_90_CLOCKWISE,
+                180 => ROTATE_180,
+                _ => ROTATE_90_COUNTERCLOCKWISE,
+            };
+
+            let mut rotated = Mat::default();
+            rotate(frame, &mut rotated, code)?;
+
+            rotated_frame = rotated;
+            &rotated_frame
+        }
+        _ => frame,
+    };
+
+    // detail finer than a cell only turns into noise, so it's blurred away at the source resolution
+    let blurred_frame;
+    let frame = if options.blur > 0 {
+        let kernel_size = options.blur * 2 + 1;
+
+        let mut blurred = Mat::default();
+        gaussian_blur(frame, &mut blurred, Size::new(kernel_size, kernel_size), 0.0, 0.0, BORDER_DEFAULT)?;
+
+        blurred_frame = blurred;
+        &blurred_frame
+    } else {
+        frame
+    };
+
+    let mut resized_frame = Mat::default();
+    let (cell_width, cell_height) = options.cell_pixels();
+    let target_size = Size::new(size_x * cell_width, size_y * cell_height);
+
+    // --aa samples every cell several times per side first and box-averages the samples, so a cell's brightness
+    // is the mean of its whole patch whatever interpolation picked them, instead of the one pixel under it
+    let sample_size = if options.antialias {
+        Size::new(target_size.width * ANTIALIAS_SAMPLES, target_size.height * ANTIALIAS_SAMPLES)
+    } else {
+        target_size
+    };
+
+    let interpolation = match options.interpolation {
+        // area averages the source pixels which is much cleaner when shrinking, the usual case here
+        Interpolation::Auto if sample_size.width <= frame.cols() && sample_size.height <= frame.rows() => INTER_AREA,
+        Interpolation::Auto => INTER_CUBIC,
+        Interpolation::Nearest => INTER_NEAREST,
+        Interpolation::Linear => INTER_LINEAR,
+        Interpolation::Cubic => INTER_CUBIC,
+        Interpolation::Area => INTER_AREA,
+    };
+
+    resize(frame, &mut resized_frame, sample_size, 0.0, 0.0, interpolation)?;
+
+    if options.antialias {
+        let mut averaged_frame = Mat::default();
+        resize(&resized_frame, &mut averaged_frame, target_size, 0.0, 0.0, INTER_AREA)?;
+        resized_frame = averaged_frame;
+    }
+
+    // flipping comes out the same before or after resizing, after has far fewer pixels to move
+    if options.mirror {
+        let mut mirrored_frame = Mat::default();
+        flip(&resized_frame, &mut mirrored_frame, 1)?;
+        resized_frame = mirrored_frame;
+    }
+
+    if options.sharpen > 0.0 {
+        return sharpen_frame(&resized_frame, options.sharpen);
+    }
+
+    Ok(resized_frame)
+}
+
+// 3x3 laplacian sharpening, amount scales how much each pixel is pushed away from its neighbours
+fn sharpen_frame(frame: &Mat, amount: f64) -> Result<Mat, opencv::Error> {
+    let mut kernel = Mat::new_rows_cols_with_default(3, 3, CV_32F, Scalar::all(0.0))?;
+
+    *kernel.at_2d_mut::<f32>(0, 1)? = -amount as f32;
+    *kernel.at_2d_mut::<f32>(1, 0)? = -amount as f32;
+    *kernel.at_2d_mut::<f32>(1, 1)? = (1.0 + 4.0 * amount) as f32;
+    *kernel.at_2d_mut::<f32>(1, 2)? = -amount as f32;
+    *kernel.at_2d_mut::<f32>(2, 1)? = -amount as f32;
+
+    let mut sharpened_frame = Mat::default();
+    filter_2d(frame, &mut sharpened_frame, -1, &kernel, Point::new(-1, -1), 0.0, BORDER_DEFAULT)?;
+
+    Ok(sharpened_frame)
+}
+
+// The part of the frame inside crop, in source pixels
+pub fn crop_frame(frame: &Mat, crop: Rect) -> Result<Mat, opencv::Error> {
+    let inside = crop.x >= 0 && crop.y >= 0 && crop.width > 0 && crop.height > 0
+        && crop.x + crop.width <= frame.cols() && crop.y + crop.height <= frame.rows();
+
+    if !inside {
+        return Err(opencv::Error::new(
+            opencv::core::StsOutOfRange,
+            format!(
+                "Crop {},{},{},{} doesn't fit inside the {}x{} frame",
+                crop.x, crop.y, crop.width, crop.height, frame.cols(), frame.rows(),
+            ),
+        ));
+    }
+
+    Mat::roi(frame, crop)
+}
+
+pub fn resize_frames(frames: Vec<Mat>, size_x: i32, size_y: i32, options: &RenderOptions) -> Result<Vec<Mat>, opencv::Error> {
+    let mut resized_frames = Vec::new();
+    let mut smoother = FrameSmoother::new(options.smooth);
+
+    let mut i = 0;
+    while i < frames.len() {
+        let resized_frame = resize_frame(&frames[i], size_x, size_y, options)?;
+
+        resized_frames.push(smoother.smooth(resized_frame)?);
+        i += 1;
+    }
+
+    Ok(resized_frames)
+}
+
+// --smooth, an exponential moving average over resized frames so static areas stop flickering between
+// neighbouring shades. factor is how much of the running average each new frame keeps, 0 turns it off
+pub struct FrameSmoother {
+    factor: f64,
+    average: Option<Mat>, // kept as floats so small changes aren't rounded away every frame
+}
+
+impl FrameSmoother {
+    pub fn new(factor: f64) -> FrameSmoother {
+        FrameSmoother { factor: factor.clamp(0.0, 1.0), average: None }
+    }
+
+    pub fn smooth(&mut self, frame: Mat) -> Result<Mat, opencv::Error> {
+        if self.factor == 0.0 {
+            return Ok(frame);
+        }
+
+        let mut current = Mat::default();
+        frame.convert_to(&mut current, CV_32F, 1.0, 0.0)?;
+
+        // the first frame, or a resolution change mid-stream, starts the average over
+        let average = match &self.average {
+            Some(average) if average.size()? == current.size()? => {
+                let mut blended = Mat::default();
+                add_weighted(average, self.factor, &current, 1.0 - self.factor, 0.0, &mut blended, -1)?;
+                blended
+            }
+            _ => current,
+        };
+
+        let mut smoothed_frame = Mat::default();
+        average.convert_to(&mut smoothed_frame, CV_8U, 1.0, 0.0)?;
+        self.average = Some(average);
+
+        Ok(smoothed_frame)
+    }
+}
+
+pub fn frame_to_text(frame: &Mat, size_x: i32, size_y: i32, options: &RenderOptions) -> Result<String, opencv::Error> {
+    let mut frame_text = String::new();
+    let use_color = options.color;
+
+    // some codecs hand back grayscale or empty mats, at_2d would read garbage or fail halfway through
+    if frame.channels() != 3 {
+        return Err(opencv::Error::new(
+            opencv::core::StsUnmatchedFormats,
+            format!("Expected a 3 channel frame, got {} channel(s)", frame.channels()),
+        ));
+    }
+
+    let (cell_width, cell_height) = options.cell_pixels();
+    let requested_size = Size::new(size_x, size_y);
+
+    // videos that change resolution mid-stream can hand over a smaller frame than asked for, it gets
+    // converted at the size it really has and padded back out so at_2d never reads past the edge
+    let size_x = size_x.min(frame.cols() / cell_width);
+    let size_y = size_y.min(frame.rows() / cell_height);
+
+    if size_x <= 0 || size_y <= 0 {
+        return Err(opencv::Error::new(
+            opencv::core::StsUnmatchedSizes,
+            format!("Frame is too small to convert, got {}x{}", frame.cols(), frame.rows()),
+        ));
+    }
+
+    // every renderer reads the adjusted copies, neutral settings skip making them
+    let recolored_frame;
+    let frame = if options.saturation != 1.0 || options.hue_shift != 0.0 {
+        recolored_frame = adjust_color(frame, options)?;
+        &recolored_frame
+    } else {
+        frame
+    };
+
+    let adjusted_frame;
+    let frame = if options.adjusts_tone() {
+        adjusted_frame = adjust_tone(frame, options)?;
+        &adjusted_frame
+    } else {
+        frame
+    };
+
+    let snapped_frame;
+    let frame = if !options.palette.is_empty() {
+        snapped_frame = snap_to_palette(frame, &options.palette)?;
+        &snapped_frame
+    } else {
+        frame
+    };
+
+    if options.background {
+        return Ok(letterbox(frame_to_half_blocks(frame, size_x, size_y, options)?, size_x, size_y, requested_size, options));
+    }
+
+    if options.mode == Mode::Edges {
+        return Ok(letterbox(frame_to_edges(frame, size_x, size_y, options)?, size_x, size_y, requested_size, options));
+    }
+
+    if options.mode == Mode::Braille {
+        return Ok(letterbox(frame_to_braille(frame, size_x, size_y, options)?, size_x, size_y, requested_size, options));
+    }
+
+    let dithered = match options.dither {
+        _ if options.mode == Mode::Pixels => None,
+        Dither::FloydSteinberg => Some(dither_frame(frame, size_x, size_y, &options.shades)?),
+        Dither::Ordered => Some(ordered_dither_frame(frame, size_x, size_y, &options.shades)?),
+        Dither::None => None,
+    };
+
+    // chroma mode colors with the pixel's hue and saturation at full brightness, the symbol keeps the real brightness
+    let vivid = if use_color && options.mode == Mode::Chroma { Some(vivid_frame(frame)?) } else { None };
+
+    let by_luminance = dithered.is_none()
+        && (options.mode == Mode::Luminance || options.mode == Mode::Chroma || options.mode == Mode::Boxfill);
+
+    let mut y = 0;
+    while y < size_y {
+        // the hot path, a whole row's brightness at once instead of pixel by pixel
+        let luminances = if by_luminance { Some(row_luminance(frame, y, size_x)?) } else { None };
+
+        let mut x = 0;
+        while x < size_x {
+            let pixel = frame.at_2d::<Vec3b>(y, x)?;
+            let color = Color {b: pixel[0], g: pixel[1], r: pixel[2]};
+
+            let closest_char = match (&dithered, &luminances) {
+                (Some(shade_indices), _) => options.shades[shade_indices[(y * size_x + x) as usize]].symbol.clone(),
+                (None, _) if options.mode == Mode::Pixels => String::from("█"), // the color alone is the pixel
+                (None, Some(luminances)) => shade_for_luminance(luminances[x as usize], &options.shades).symbol.clone(),
+                (None, None) => color_to_character(color, options)?,
+            };
+
+            if use_color {
+                let color = match &vivid {
+                    Some(vivid) => {
+                        let pixel = vivid.at_2d::<Vec3b>(y, x)?;
+                        Color {b: pixel[0], g: pixel[1], r: pixel[2]}
+                    }
+                    None => color,
+                };
+
+                // 24-bit foreground escape, the symbol still carries the brightness
+                frame_text.push_str(&options.foreground_escape(color));
+            }
+
+            frame_text.push_str(&closest_char);
+            x += 1;
+        }
+
+        if use_color {
+            frame_text.push_str(RESET_COLOR); // reset before the newline so colors don't wrap
+        }
+    
+        frame_text.push_str("\n");
+        y += 1;
+    }
+
+    if use_color {
+        frame_text.push_str(RESET_COLOR); // make sure nothing bleeds into the prompt after playback
+    }
+
+    Ok(letterbox(frame_text, size_x, size_y, requested_size, options))
+}
+
+// Where a channel value ends up after --gamma, then --contrast around mid-gray, then --brightness
+pub fn tone_curve(value: u8, options: &RenderOptions) -> u8 {
+    let adjusted = 255.0 * (value as f64 / 255.0).powf(1.0 / options.gamma);
+    let adjusted = (adjusted - 128.0) * options.contrast + 128.0 + options.brightness;
+
+    adjusted.round().clamp(0.0, 255.0) as u8
+}
+
+// Runs every channel through tone_curve, precomputed into a 256 entry table so it's one lookup per pixel
+fn adjust_tone(frame: &Mat, options: &RenderOptions) -> Result<Mat, opencv::Error> {
+    let mut table = Mat::new_rows_cols_with_default(1, 256, CV_8U, Scalar::all(0.0))?;
+
+    let mut i = 0;
+    while i < 256 {
+        *table.at_mut::<u8>(i)? = tone_curve(i as u8, options);
+        i += 1;
+    }
+
+    let mut adjusted_frame = Mat::default();
+    lut(frame, &table, &mut adjusted_frame)?;
+
+    Ok(adjusted_frame)
+}
+
+// --saturation and --hue-shift through an HSV round trip, opencv keeps 8-bit hue as degrees / 2
+fn adjust_color(frame: &Mat, options: &RenderOptions) -> Result<Mat, opencv::Error> {
+    let mut hsv_frame = Mat::default();
+    cvt_color(frame, &mut hsv_frame, COLOR_BGR2HSV, 0)?;
+
+    let hue_shift = (options.hue_shift / 2.0).round() as i32;
+
+    let mut y = 0;
+    while y < hsv_frame.rows() {
+        let mut x = 0;
+        while x < hsv_frame.cols() {
+            let pixel = hsv_frame.at_2d_mut::<Vec3b>(y, x)?;
+
+            pixel[0] = (pixel[0] as i32 + hue_shift).rem_euclid(180) as u8;
+            pixel[1] = (pixel[1] as f64 * options.saturation).round().clamp(0.0, 255.0) as u8;
+            x += 1;
+        }
+        y += 1;
+    }
+
+    let mut adjusted_frame = Mat::default();
+    cvt_color(&hsv_frame, &mut adjusted_frame, COLOR_HSV2BGR, 0)?;
+
+    Ok(adjusted_frame)
+}
+
+// --palette, every pixel becomes the nearest palette color before shades are picked
+fn snap_to_palette(frame: &Mat, palette: &[Color]) -> Result<Mat, opencv::Error> {
+    let mut snapped_frame = frame.clone();
+
+    let mut y = 0;
+    while y < snapped_frame.rows() {
+        let mut x = 0;
+        while x < snapped_frame.cols() {
+            let pixel = snapped_frame.at_2d_mut::<Vec3b>(y, x)?;
+            let color = Color { b: pixel[0], g: pixel[1], r: pixel[2] };
+
+            let mut nearest = palette[0];
+            for &entry in palette {
+                if color_distance(color, entry) < color_distance(color, nearest) {
+                    nearest = entry;
+                }
+            }
+
+            *pixel = Vec3b::from([nearest.b, nearest.g, nearest.r]);
+            x += 1;
+        }
+        y += 1;
+    }
+
+    Ok(snapped_frame)
+}
+
+// Same hue and saturation with every pixel's value maxed out
+fn vivid_frame(frame: &Mat) -> Result<Mat, opencv::Error> {
+    let mut hsv_frame = Mat::default();
+    cvt_color(frame, &mut hsv_frame, COLOR_BGR2HSV, 0)?;
+
+    let mut y = 0;
+    while y < hsv_frame.rows() {
+        let mut x = 0;
+        while x < hsv_frame.cols() {
+            hsv_frame.at_2d_mut::<Vec3b>(y, x)?[2] = 255;
+            x += 1;
+        }
+        y += 1;
+    }
+
+    let mut vivid_frame = Mat::default();
+    cvt_color(&hsv_frame, &mut vivid_frame, COLOR_HSV2BGR, 0)?;
+
+    Ok(vivid_frame)
+}
+
+// Two pixels per cell: ▀ takes the top pixel as its foreground and the bottom one as the background
+fn frame_to_half_blocks(frame: &Mat, size_x: i32, size_y: i32, options: &RenderOptions) -> Result<String, opencv::Error> {
+    let mut frame_text = String::new();
+
+    let mut y = 0;
+    while y < size_y {
+        let mut x = 0;
+        while x < size_x {
+            let top = frame.at_2d::<Vec3b>(y * 2, x)?;
+            let bottom = frame.at_2d::<Vec3b>(y * 2 + 1, x)?;
+
+            frame_text.push_str(&options.foreground_escape(Color { b: top[0], g: top[1], r: top[2] }));
+            frame_text.push_str(&options.background_escape(Color { b: bottom[0], g: bottom[1], r: bottom[2] }));
+            frame_text.push('▀');
+            x += 1;
+        }
+
+        frame_text.push_str(RESET_COLOR);
+        frame_text.push_str("\n");
+        y += 1;
+    }
+
+    frame_text.push_str(RESET_COLOR);
+
+    Ok(frame_text)
+}
+
+// Each cell packs a 2x4 block of pixels into one braille character, a dot is raised for every
+// pixel darker than the threshold (lighter with --invert) to match the shade ramp
+fn frame_to_braille(frame: &Mat, size_x: i32, size_y: i32, options: &RenderOptions) -> Result<String, opencv::Error> {
+    let mut frame_text = String::new();
+    let use_color = options.color;
+
+    let mut y = 0;
+    while y < size_y {
+        let mut x = 0;
+        while x < size_x {
+            let mut dots: u32 = 0;
+            let (mut r, mut g, mut b) = (0u32, 0u32, 0u32);
+
+            for dot_y in 0..4 {
+                for dot_x in 0..2 {
+                    let pixel = frame.at_2d::<Vec3b>(y * 4 + dot_y, x * 2 + dot_x)?;
+                    let color = Color { b: pixel[0], g: pixel[1], r: pixel[2] };
+
+                    r += color.r as u32;
+                    g += color.g as u32;
+                    b += color.b as u32;
+
+                    // ordered dithering moves the cutoff around so flat gray areas become a dot pattern
+                    let threshold = match options.dither {
+                        Dither::Ordered => {
+                            let offset = (bayer_threshold(x * 2 + dot_x, y * 4 + dot_y) - 0.5) * 255.0;
+                            (options.threshold as f64 + offset).clamp(0.0, 255.0) as u8
+                        }
+                        _ => options.threshold,
+                    };
+
+                    if (luminance(color) < threshold) != options.invert {
+                        dots |= BRAILLE_DOTS[dot_y as usize][dot_x as usize];
+                    }
+                }
+            }
+
+            if use_color {
+                // the whole cell shares one color, so use the average of its pixels
+                frame_text.push_str(&options.foreground_escape(Color { r: (r / 8) as u8, g: (g / 8) as u8, b: (b / 8) as u8 }));
+            }
+
+            frame_text.push(char::from_u32(0x2800 + dots).unwrap_or(' '));
+            x += 1;
+        }
+
+        if use_color {
+            frame_text.push_str(RESET_COLOR);
+        }
+
+        frame_text.push_str("\n");
+        y += 1;
+    }
+
+    if use_color {
+        frame_text.push_str(RESET_COLOR);
+    }
+
+    Ok(frame_text)
+}
+
+// Line-art rendering, sobel gradients become - | / \ depending on which way the edge runs
+fn frame_to_edges(frame: &Mat, size_x: i32, size_y: i32, options: &RenderOptions) -> Result<String, opencv::Error> {
+    let mut frame_text = String::new();
+    let use_color = options.color;
+
+    let mut gray = Mat::default();
+    cvt_color(frame, &mut gray, COLOR_BGR2GRAY, 0)?;
+
+    let mut gradient_x = Mat::default();
+    let mut gradient_y = Mat::default();
+    sobel(&gray, &mut gradient_x, CV_32F, 1, 0, 3, 1.0, 0.0, BORDER_DEFAULT)?;
+    sobel(&gray, &mut gradient_y, CV_32F, 0, 1, 3, 1.0, 0.0, BORDER_DEFAULT)?;
+
+    let mut y = 0;
+    while y < size_y {
+        let mut x = 0;
+        while x < size_x {
+            let dx = *gradient_x.at_2d::<f32>(y, x)?;
+            let dy = *gradient_y.at_2d::<f32>(y, x)?;
+
+            let symbol = if (dx * dx + dy * dy).sqrt() < EDGE_THRESHOLD {
+                " "
+            } else {
+                // the edge runs perpendicular to the gradient, y grows downwards
+                let angle = dy.atan2(dx).to_degrees().rem_euclid(180.0);
+
+                if angle < 22.5 || angle >= 157.5 {
+                    "|"
+                } else if angle < 67.5 {
+                    "/"
+                } else if angle < 112.5 {
+                    "-"
+                } else {
+                    "\\"
+                }
+            };
+
+            if use_color {
+                let pixel = frame.at_2d::<Vec3b>(y, x)?;
+                frame_text.push_str(&options.foreground_escape(Color { b: pixel[0], g: pixel[1], r: pixel[2] }));
+            }
+
+            frame_text.push_str(symbol);
+            x += 1;
+        }
+
+        if use_color {
+            frame_text.push_str(RESET_COLOR);
+        }
+
+        frame_text.push_str("\n");
+        y += 1;
+    }
+
+    if use_color {
+        frame_text.push_str(RESET_COLOR);
+    }
+
+    Ok(frame_text)
+}
+
+// Pads the frame with spaces so it sits in the middle of options.letterbox, or of the requested size
+fn letterbox(frame_text: String, size_x: i32, size_y: i32, requested_size: Size, options: &RenderOptions) -> String {
+    // without --keep-aspect only frames that came out smaller than requested need padding
+    let target = options.letterbox.unwrap_or(requested_size);
+
+    if target.width <= size_x && target.height <= size_y {
+        return frame_text;
+    }
+
+    let pad_x = (target.width - size_x).max(0) as usize;
+    let pad_y = (target.height - size_y).max(0) as usize;
+
+    let left = " ".repeat(pad_x / 2);
+    let right = " ".repeat(pad_x - pad_x / 2);
+    let blank_line = " ".repeat(target.width.max(size_x) as usize);
+
+    let mut boxed_text = String::new();
+
+    for _ in 0..pad_y / 2 {
+        boxed_text.push_str(&blank_line);
+        boxed_text.push_str("\n");
+    }
+
+    // take skips the trailing reset left after the last newline in color mode
+    for line in frame_text.lines().take(size_y as usize) {
+        boxed_text.push_str(&left);
+        boxed_text.push_str(line);
+        boxed_text.push_str(&right);
+        boxed_text.push_str("\n");
+    }
+
+    for _ in 0..pad_y - pad_y / 2 {
+        boxed_text.push_str(&blank_line);
+        boxed_text.push_str("\n");
+    }
+
+    // half blocks always carry colors, even where options.color is off
+    if options.color || options.background {
+        boxed_text.push_str(RESET_COLOR);
+    }
+
+    boxed_text
+}
+
+// Nearest entry of the xterm 256 color palette, either the 6x6x6 cube or the 24 step gray ramp
+pub fn nearest_ansi256(color: Color) -> u8 {
+    let distance = |a: Color, b: Color| {
+        (a.r as i32 - b.r as i32).pow(2) + (a.g as i32 - b.g as i32).pow(2) + (a.b as i32 - b.b as i32).pow(2)
+    };
+
+    // each channel on its own to the closest of the cube's levels
+    let cube_index = |value: u8| {
+        let mut closest = 0;
+        for (i, level) in ANSI256_CUBE_LEVELS.iter().enumerate() {
+            if (*level as i32 - value as i32).abs() < (ANSI256_CUBE_LEVELS[closest] as i32 - value as i32).abs() {
+                closest = i;
+            }
+        }
+        closest
+    };
+
+    let (r, g, b) = (cube_index(color.r), cube_index(color.g), cube_index(color.b));
+    let cube_color = Color { r: ANSI256_CUBE_LEVELS[r], g: ANSI256_CUBE_LEVELS[g], b: ANSI256_CUBE_LEVELS[b] };
+    let cube = 16 + 36 * r as u8 + 6 * g as u8 + b as u8;
+
+    // gray ramp runs 8, 18, ... 238
+    let average = (color.r as u32 + color.g as u32 + color.b as u32) / 3;
+    let gray_step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + gray_step * 10;
+    let gray_color = Color { r: gray_level, g: gray_level, b: gray_level };
+
+    if distance(color, gray_color) < distance(color, cube_color) {
+        232 + gray_step
+    } else {
+        cube
+    }
+}
+
+pub fn ansi256_to_color(index: u8) -> Color {
+    match index {
+        0..=15 => {
+            // the basic colors depend on the terminal theme, these are the usual xterm ones
+            let (r, g, b) = ANSI16_COLORS[index as usize];
+            Color { r, g, b }
+        }
+        16..=231 => {
+            let cube = index - 16;
+            Color {
+                r: ANSI256_CUBE_LEVELS[(cube / 36) as usize],
+                g: ANSI256_CUBE_LEVELS[(cube / 6 % 6) as usize],
+                b: ANSI256_CUBE_LEVELS[(cube % 6) as usize],
+            }
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            Color { r: level, g: level, b: level }
+        }
+    }
+}
+
+// NO_COLOR asks for plain output, any value counts except an empty one
+pub fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").map_or(false, |no_color| !no_color.is_empty())
+}
+
+// Picks the color depth for --color auto. Nothing when $NO_COLOR is set (https://no-color.org), stdout isn't
+// a terminal (piped into a file or another program) or $TERM can't do color, truecolor when $COLORTERM
+// advertises it, then 256 colors for 256color terminals
+pub fn detect_color_mode() -> ColorMode {
+    if no_color() {
+        return ColorMode::None;
+    }
+
+    if !stdout().is_terminal() {
+        return ColorMode::None;
+    }
+
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default().to_lowercase();
+    let term = std::env::var("TERM").unwrap_or_default().to_lowercase();
+
+    if term.is_empty() || term == "dumb" {
+        ColorMode::None
+    } else if colorterm.contains("truecolor") || colorterm.contains("24bit") {
+        ColorMode::Truecolor
+    } else if term.contains("256color") || !colorterm.is_empty() {
+        ColorMode::Ansi256
+    } else {
+        ColorMode::None // 8 or 16 color terminals, the shades alone look better than 16 colors
+    }
+}
+
+// Floyd-Steinberg error diffusion over the frame's luminance, returns the chosen shade index per cell
+fn dither_frame(frame: &Mat, size_x: i32, size_y: i32, shades: &[Shade]) -> Result<Vec<usize>, opencv::Error> {
+    let width = size_x as usize;
+    let height = size_y as usize;
+
+    let mut brightness = vec![0.0f32; width * height];
+
+    let mut y = 0;
+    while y < size_y {
+        let mut x = 0;
+        while x < size_x {
+            let pixel = frame.at_2d::<Vec3b>(y, x)?;
+            brightness[(y * size_x + x) as usize] = luminance(Color { b: pixel[0], g: pixel[1], r: pixel[2] }) as f32;
+            x += 1;
+        }
+        y += 1;
+    }
+
+    let levels: Vec<f32> = shades.iter().map(|shade| luminance(shade.color) as f32).collect();
+    let mut shade_indices = vec![0; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let old = brightness[i];
+
+            // closest shade level to the (already error adjusted) brightness
+            let mut closest = 0;
+            for (level_index, level) in levels.iter().enumerate() {
+                if (level - old).abs() < (levels[closest] - old).abs() {
+                    closest = level_index;
+                }
+            }
+
+            shade_indices[i] = closest;
+            let error = old - levels[closest];
+
+            if x + 1 < width {
+                brightness[i + 1] += error * 7.0 / 16.0;
+            }
+
+            if y + 1 < height {
+                if x > 0 {
+                    brightness[i + width - 1] += error * 3.0 / 16.0;
+                }
+
+                brightness[i + width] += error * 5.0 / 16.0;
+
+                if x + 1 < width {
+                    brightness[i + width + 1] += error * 1.0 / 16.0;
+                }
+            }
+        }
+    }
+
+    Ok(shade_indices)
+}
+
+// 4x4 Bayer matrix, each cell's rank in the pattern that spreads out evenly as brightness rises
+const BAYER_MATRIX: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+// Where between 0 and 1 the pixel at x, y flips to the next level, tiled over the frame
+fn bayer_threshold(x: i32, y: i32) -> f64 {
+    (BAYER_MATRIX[(y % 4) as usize][(x % 4) as usize] as f64 + 0.5) / 16.0
+}
+
+// Ordered dithering between the two shade levels around each pixel's brightness, the further it is
+// towards the upper one the more pixels of the Bayer pattern get it. Nothing carries over between pixels
+fn ordered_dither_frame(frame: &Mat, size_x: i32, size_y: i32, shades: &[Shade]) -> Result<Vec<usize>, opencv::Error> {
+    let levels: Vec<f64> = shades.iter().map(|shade| luminance(shade.color) as f64).collect();
+    let mut shade_indices = Vec::with_capacity((size_x * size_y) as usize);
+
+    let mut y = 0;
+    while y < size_y {
+        let mut x = 0;
+        while x < size_x {
+            let pixel = frame.at_2d::<Vec3b>(y, x)?;
+            let brightness = luminance(Color { b: pixel[0], g: pixel[1], r: pixel[2] }) as f64;
+
+            shade_indices.push(ordered_shade(brightness, bayer_threshold(x, y), &levels));
+            x += 1;
+        }
+        y += 1;
+    }
+
+    Ok(shade_indices)
+}
+
+fn ordered_shade(brightness: f64, threshold: f64, levels: &[f64]) -> usize {
+    // the closest level at or below the brightness and the closest one above it, the ramp can be in any order
+    let mut below: Option<usize> = None;
+    let mut above: Option<usize> = None;
+
+    for (i, &level) in levels.iter().enumerate() {
+        if level <= brightness && below.map_or(true, |below| level > levels[below]) {
+            below = Some(i);
+        }
+
+        if level > brightness && above.map_or(true, |above| level < levels[above]) {
+            above = Some(i);
+        }
+    }
+
+    match (below, above) {
+        (Some(below), Some(above)) => {
+            let position = (brightness - levels[below]) / (levels[above] - levels[below]);
+            if position > threshold { above } else { below }
+        }
+        (Some(only), None) | (None, Some(only)) => only,
+        (None, None) => 0,
+    }
+}
+
+pub fn default_shades() -> Vec<Shade> {
+    vec![
+        Shade { symbol: String::from("█"), color: Color { r: 0, g: 0, b: 0 } },
+        Shade { symbol: String::from("▓"), color: Color { r: 51, g: 51, b: 51 } },
+        Shade { symbol: String::from("▒"), color: Color { r: 153, g: 153, b: 153 } },
+        Shade { symbol: String::from("░"), color: Color { r: 204, g: 204, b: 204 } },
+        Shade { symbol: String::from(" "), color: Color { r: 255, g: 255, b: 255 } },
+    ]
+}
+
+// A shade for every gray level drawn with the symbol of the nearest --shade pair, so uneven
+// spacing survives the luminance and rgb lookups that expect an even ramp
+pub fn pairs_to_shades(pairs: &[(u8, char)]) -> Result<Vec<Shade>> {
+    if pairs.is_empty() {
+        anyhow::bail!("At least one shade is needed");
+    }
+
+    for &(_, symbol) in pairs {
+        check_symbol_width(symbol)?;
+    }
+
+    let mut shades = Vec::new();
+
+    let mut level = 0;
+    while level < 256 {
+        let mut nearest = pairs[0];
+
+        let mut i = 1;
+        while i < pairs.len() {
+            if (pairs[i].0 as i32 - level).abs() < (nearest.0 as i32 - level).abs() {
+                nearest = pairs[i];
+            }
+            i += 1;
+        }
+
+        let gray = level as u8;
+        shades.push(Shade {
+            symbol: nearest.1.to_string(),
+            color: Color { r: gray, g: gray, b: gray },
+        });
+        level += 1;
+    }
+
+    Ok(shades)
+}
+
+// Colors for --palette, one per line as #rrggbb or r,g,b. Blank lines and lines starting with ; are skipped
+pub fn load_palette(path: &str) -> Result<Vec<Color>> {
+    let data = std::fs::read_to_string(path).with_context(|| format!("Couldn't read the palette {}", path))?;
+    let mut palette = Vec::new();
+
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+
+        let color = parse_palette_color(line)
+            .with_context(|| format!("Line {} of {} isn't a color, use #rrggbb or r,g,b: {}", i + 1, path, line))?;
+        palette.push(color);
+    }
+
+    if palette.is_empty() {
+        anyhow::bail!("The palette {} has no colors", path);
+    }
+
+    Ok(palette)
+}
+
+fn parse_palette_color(line: &str) -> Option<Color> {
+    if let Some(hex) = line.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color { r: channel(0)?, g: channel(2)?, b: channel(4)? });
+    }
+
+    let channels: Vec<u8> = line.split(',').map(|channel| channel.trim().parse().ok()).collect::<Option<_>>()?;
+
+    match channels.as_slice() {
+        [r, g, b] => Some(Color { r: *r, g: *g, b: *b }),
+        _ => None,
+    }
+}
+
+// SubRip cues for --subs. Blocks are separated by blank lines, the number line before the timing is ignored
+// and formatting tags like <i> are dropped since the terminal can't show them
+pub fn load_subtitles(path: &str) -> Result<Vec<Subtitle>> {
+    let data = std::fs::read_to_string(path).with_context(|| format!("Couldn't read the subtitles {}", path))?;
+    parse_subtitles(&data).with_context(|| format!("Couldn't parse the subtitles {}", path))
+}
+
+fn parse_subtitles(data: &str) -> Result<Vec<Subtitle>> {
+    let mut subtitles: Vec<Subtitle> = Vec::new();
+    let mut in_cue = false;
+
+    for (i, line) in data.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            in_cue = false;
+            continue;
+        }
+
+        if let Some((start, end)) = line.split_once("-->") {
+            let time = |time: &str| parse_srt_time(time.trim());
+
+            let (start, end) = time(start).zip(time(end))
+                .with_context(|| format!("Line {} isn't a cue timing like 00:00:01,000 --> 00:00:02,500: {}", i + 1, line))?;
+
+            subtitles.push(Subtitle { start, end, text: String::new() });
+            in_cue = true;
+            continue;
+        }
+
+        // anything else outside a cue is its number
+        if !in_cue {
+            continue;
+        }
+
+        let subtitle = subtitles.last_mut().expect("in_cue is only set after a cue is pushed");
+        if !subtitle.text.is_empty() {
+            subtitle.text.push('\n');
+        }
+
+        let mut in_tag = false;
+        for ch in line.chars() {
+            match ch {
+                '<' => in_tag = true,
+                '>' if in_tag => in_tag = false,
+                _ if !in_tag => subtitle.text.push(ch),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(subtitles)
+}
+
+// HH:MM:SS,mmm into ms, some files use a . before the milliseconds
+fn parse_srt_time(time: &str) -> Option<u64> {
+    let (clock, millis) = time.split_once([',', '.'])?;
+    let units: Vec<u64> = clock.split(':').map(|unit| unit.parse().ok()).collect::<Option<_>>()?;
+
+    match units.as_slice() {
+        [hours, minutes, seconds] => Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis.parse::<u64>().ok()?),
+        _ => None,
+    }
+}
+
+// The cue on screen at ms into the video, the index into subtitles so frames can tell whether it changed
+fn active_subtitle(subtitles: &[Subtitle], millis: u64) -> Option<usize> {
+    subtitles.iter().position(|subtitle| subtitle.start <= millis && millis < subtitle.end)
+}
+
+// The eighth blocks as an evenly spaced ramp, for --mode boxfill
+pub fn boxfill_shades() -> Vec<Shade> {
+    let mut shades = Vec::new();
+
+    let mut i = 0;
+    while i < BOXFILL_RAMP.len() {
+        let level = (i * 255 / (BOXFILL_RAMP.len() - 1)) as u8;
+
+        shades.push(Shade {
+            symbol: BOXFILL_RAMP[i].to_string(),
+            color: Color { r: level, g: level, b: level },
+        });
+        i += 1;
+    }
+
+    shades
+}
+
+// The dark symbol for black and the light one for white, for --mode threshold
+pub fn two_tone_shades(on: char, off: char) -> Result<Vec<Shade>> {
+    check_symbol_width(on)?;
+    check_symbol_width(off)?;
+
+    Ok(vec![
+        Shade { symbol: on.to_string(), color: Color { r: 0, g: 0, b: 0 } },
+        Shade { symbol: off.to_string(), color: Color { r: 255, g: 255, b: 255 } },
+    ])
+}
+
+// Every cell is assumed to be one column, wide characters (emoji, CJK) or combining marks would skew the grid
+fn check_symbol_width(symbol: char) -> Result<()> {
+    if symbol.width() != Some(1) {
+        anyhow::bail!(
+            "{:?} (U+{:04X}) isn't one column wide in a terminal, pick a single-width character",
+            symbol, symbol as u32,
+        );
+    }
+
+    Ok(())
+}
+
+pub fn charset_to_shades(charset: &str) -> Result<Vec<Shade>> {
+    // leftmost character is the brightest, so walk it backwards to go dark -> bright
+    let symbols: Vec<char> = charset.chars().rev().collect();
+
+    if symbols.is_empty() {
+        anyhow::bail!("Charset must contain at least one character");
+    }
+
+    for &symbol in &symbols {
+        check_symbol_width(symbol)?;
+    }
+
+    let mut shades = Vec::new();
+    let steps = (symbols.len() - 1).max(1) as u32;
+
+    let mut i = 0;
+    while i < symbols.len() {
+        let level = (i as u32 * 255 / steps) as u8;
+
+        shades.push(Shade {
+            symbol: symbols[i].to_string(),
+            color: Color { r: level, g: level, b: level },
+        });
+        i += 1;
+    }
+
+    Ok(shades)
+}
+
+// Evenly spaced grays from black to white, 2 levels is a plain black/white threshold
+pub fn levels_to_shades(levels: usize) -> Result<Vec<Shade>> {
+    let ramp: Vec<String> = if levels <= BLOCK_RAMP.len() {
+        BLOCK_RAMP.iter().map(|symbol| symbol.to_string()).collect()
+    } else {
+        ASCII_RAMP.chars().map(|symbol| symbol.to_string()).collect()
+    };
+
+    if levels < 2 || levels > ramp.len() {
+        anyhow::bail!("Levels must be between 2 and {}", ASCII_RAMP.chars().count());
+    }
+
+    let mut shades = Vec::new();
+
+    let mut i = 0;
+    while i < levels {
+        let symbol = ramp[i * (ramp.len() - 1) / (levels - 1)].clone();
+        let level = (i * 255 / (levels - 1)) as u8;
+
+        shades.push(Shade { symbol, color: Color { r: level, g: level, b: level } });
+        i += 1;
+    }
+
+    Ok(shades)
+}
+
+// Flip which symbol covers dark vs light, the shade colors stay put so both modes see the swap
+pub fn invert_shades(shades: &mut [Shade]) {
+    let mut symbols: Vec<String> = shades.iter().map(|shade| shade.symbol.clone()).collect();
+    symbols.reverse();
+
+    for (shade, symbol) in shades.iter_mut().zip(symbols) {
+        shade.symbol = symbol;
+    }
+}
+
+pub fn luminance(color: Color) -> u8 {
+    (0.299 * color.r as f64 + 0.587 * color.g as f64 + 0.114 * color.b as f64) as u8
+}
+
+// Luminance of the first size_x pixels in row y, four at a time in SIMD lanes. wide falls back to
+// plain scalar code on targets without SIMD, and the lanes do the same math as luminance() so both agree
+fn row_luminance(frame: &Mat, y: i32, size_x: i32) -> Result<Vec<u8>, opencv::Error> {
+    let cols = frame.cols() as usize;
+    let copied: Vec<u8>;
+
+    // continuous mats are one BGR buffer so the row is a plain slice of it, ROIs like --crop copy it out instead
+    let row: &[u8] = if frame.is_continuous() {
+        let start = y as usize * cols * 3;
+        &frame.data_bytes()?[start..start + cols * 3]
+    } else {
+        copied = frame.at_row::<Vec3b>(y)?.iter().flat_map(|pixel| pixel.0).collect();
+        &copied
+    };
+
+    let size_x = (size_x as usize).min(cols);
+    let mut luminances = Vec::with_capacity(size_x);
+
+    let mut x = 0;
+    while x + 4 <= size_x {
+        let pixels = &row[x * 3..(x + 4) * 3];
+        let channel = |c: usize| f64x4::from([pixels[c] as f64, pixels[3 + c] as f64, pixels[6 + c] as f64, pixels[9 + c] as f64]);
+
+        // mats are BGR
+        let lanes = f64x4::splat(0.299) * channel(2) + f64x4::splat(0.587) * channel(1) + f64x4::splat(0.114) * channel(0);
+
+        for lane in lanes.to_array() {
+            luminances.push(lane as u8);
+        }
+        x += 4;
+    }
+
+    // whatever doesn't fill a whole vector
+    while x < size_x {
+        let pixel = &row[x * 3..x * 3 + 3];
+        luminances.push(luminance(Color { b: pixel[0], g: pixel[1], r: pixel[2] }));
+        x += 1;
+    }
+
+    Ok(luminances)
+}
+
+// shades run dark -> bright, so the brightness bucket is the index
+fn shade_for_luminance(luminance: u8, shades: &[Shade]) -> &Shade {
+    &shades[luminance as usize * shades.len() / 256]
+}
+
+// Squared RGB distance, signed differences since saturating_sub clamped to 0 whenever b was brighter
+fn color_distance(a: Color, b: Color) -> u32 {
+    (a.r as i32 - b.r as i32).pow(2) as u32 +
+        (a.g as i32 - b.g as i32).pow(2) as u32 +
+        (a.b as i32 - b.b as i32).pow(2) as u32
+}
+
+pub fn color_to_character(color: Color, options: &RenderOptions) -> Result<String, opencv::Error> {
+    let shades = &options.shades;
+
+    if options.mode == Mode::Luminance || options.mode == Mode::Chroma || options.mode == Mode::Boxfill {
+        return Ok(shade_for_luminance(luminance(color), shades).symbol.clone());
+    }
+
+    // darkest shade below the cutoff, brightest above, --invert has already swapped the ends
+    if options.mode == Mode::Threshold {
+        let shade = if luminance(color) < options.threshold { &shades[0] } else { &shades[shades.len() - 1] };
+        return Ok(shade.symbol.clone());
+    }
+
+    // match the color to the closest color in the shades vector
+    let mut i = 0;
+    let mut closest_distance: u32 = u32::MAX;
+    let mut character = String::new();
+
+    while i < shades.len() {
+        let shade = &shades[i];
+
+        let distance = color_distance(color, shade.color);
+
+        if distance < closest_distance {
+            closest_distance = distance;
+            character = shade.symbol.clone();
+        }
+
+        i += 1;
+    }
+
+    Ok(character)
+}
+
+// Where the frame's top left corner goes so it sits in the middle of the terminal
+fn centered_offset(frame_text: &str) -> (u16, u16) {
+    let (Width(width), Height(height)) = match terminal_size() {
+        Some(size) => size,
+        None => return (0, 0),
+    };
+
+    // escapes take no space, so only count the visible cells
+    let columns = frame_text.lines().next().map_or(0, |line| parse_ansi_line(line).len()) as u16;
+    let rows = frame_text.matches('\n').count() as u16;
+
+    (width.saturating_sub(columns) / 2, height.saturating_sub(rows) / 2)
+}
+
+// With a previous frame only the lines that changed since it are written
+fn draw_frame<W: Write>(out: &mut W, frame_text: &str, previous: Option<&str>, center: bool) -> crossterm::Result<()> {
+    // looked up every frame so it follows the terminal when it's resized
+    let (offset_x, offset_y) = if center { centered_offset(frame_text) } else { (0, 0) };
+
+    let mut previous_lines = previous.map(|previous| previous.lines());
+
+    // raw mode doesn't return the carriage on \n, so every line gets its own MoveTo
+    for (row, line) in frame_text.lines().enumerate() {
+        // lines carry their own color escapes and reset, so an unchanged one can be left as it is
+        if previous_lines.as_mut().and_then(|lines| lines.next()) == Some(line) {
+            continue;
+        }
+
+        queue!(
+            out,
+            // Clear(ClearType::All),
+            MoveTo(offset_x, offset_y + row as u16),
+            Print(line),
+        )?;
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+// What --motion-threshold last put on screen, the brightness of every cell and where the frame started
+#[derive(Default)]
+struct ShownCells {
+    offset: (u16, u16),
+    levels: Vec<u8>,
+}
+
+// Average brightness of each cell's block of pixels, row by row, with the number of cells in a row
+fn cell_luminance(frame: &Mat, size_x: i32, size_y: i32, options: &RenderOptions) -> Result<(usize, Vec<u8>), opencv::Error> {
+    let (cell_width, cell_height) = options.cell_pixels();
+    let size_x = size_x.min(frame.cols() / cell_width).max(0);
+    let size_y = size_y.min(frame.rows() / cell_height).max(0);
+
+    let mut levels = Vec::with_capacity((size_x * size_y) as usize);
+
+    let mut y = 0;
+    while y < size_y {
+        let mut x = 0;
+        while x < size_x {
+            let mut total = 0;
+
+            for dy in 0..cell_height {
+                for dx in 0..cell_width {
+                    let pixel = frame.at_2d::<Vec3b>(y * cell_height + dy, x * cell_width + dx)?;
+                    total += luminance(Color {b: pixel[0], g: pixel[1], r: pixel[2]}) as i32;
+                }
+            }
+
+            levels.push((total / (cell_width * cell_height)) as u8);
+            x += 1;
+        }
+        y += 1;
+    }
+
+    Ok((size_x as usize, levels))
+}
+
+// Splits a rendered line into its cells, each one keeps the color escape in front of it.
+// The reset at the end of a colored line isn't a cell
+fn line_cells(line: &str) -> Vec<&str> {
+    let mut cells = Vec::new();
+    let mut start = 0;
+    let mut in_escape = false;
+
+    for (i, ch) in line.char_indices() {
+        if in_escape {
+            in_escape = ch != 'm';
+        } else if ch == '\x1b' {
+            in_escape = true;
+        } else {
+            cells.push(&line[start..i + ch.len_utf8()]);
+            start = i + ch.len_utf8();
+        }
+    }
+
+    cells
+}
+
+// Like draw_frame but cell by cell for live video: a cell is only redrawn once its brightness moves more than
+// threshold away from what's on screen, so a mostly still camera sends a few cells a frame instead of whole lines.
+// levels covers the picture without its letterbox padding, which stays blank and is only drawn the first time
+fn draw_moving_cells<W: Write>(
+    out: &mut W,
+    frame_text: &str,
+    cells_x: usize,
+    levels: &[u8],
+    threshold: u8,
+    center: bool,
+    shown: &mut ShownCells,
+) -> crossterm::Result<()> {
+    let offset = if center { centered_offset(frame_text) } else { (0, 0) };
+    let rows: Vec<Vec<&str>> = frame_text.lines().map(line_cells).collect();
+
+    let columns = rows.first().map_or(0, |cells| cells.len());
+    let cells_y = if cells_x > 0 { levels.len() / cells_x } else { 0 };
+    let pad_x = columns.saturating_sub(cells_x) / 2;
+    let pad_y = rows.len().saturating_sub(cells_y) / 2;
+
+    // the first frame, or the terminal moved it, so everything gets drawn
+    let redraw = shown.offset != offset || shown.levels.len() != rows.len() * columns;
+    if redraw {
+        shown.offset = offset;
+        shown.levels = vec![0; rows.len() * columns];
+    }
+
+    let color = frame_text.contains('\x1b');
+
+    for (row, cells) in rows.iter().enumerate() {
+        let mut x = 0;
+        while x < cells.len() {
+            let start = x;
+
+            // a run of changed cells shares one MoveTo
+            while x < cells.len() {
+                let level = match (x.checked_sub(pad_x), row.checked_sub(pad_y)) {
+                    (Some(cell_x), Some(cell_y)) if cell_x < cells_x && cell_y < cells_y => levels[cell_y * cells_x + cell_x],
+                    _ => 0, // padding
+                };
+
+                let index = row * columns + x;
+                if index >= shown.levels.len() || !(redraw || shown.levels[index].abs_diff(level) > threshold) {
+                    break;
+                }
+
+                shown.levels[index] = level;
+                x += 1;
+            }
+
+            if x > start {
+                queue!(out, MoveTo(offset.0 + start as u16, offset.1 + row as u16), Print(cells[start..x].concat()))?;
+
+                if color {
+                    queue!(out, Print(RESET_COLOR))?;
+                }
+            } else {
+                x += 1;
+            }
+        }
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+// space pauses/resumes, right arrow steps one frame while paused, q or Ctrl-C quits.
+// Frames go to out, keys still come from the terminal and are only read when stdout is one
+pub fn print_frames<W: Write>(
+    out: &mut W,
+    frames_text: &[Rc<String>],
+    playback: &PlaybackOptions,
+    audio: Option<&AudioPlayback>,
+) -> crossterm::Result<()> {
+    let frame_delay = playback.frame_delay;
+    let plays = playback.plays;
+    let delay = Duration::from_millis(frame_delay);
+
+    let mut previous: Option<usize> = None; // frame currently on screen, for --diff
+
+    // identical frames in a row (slideshows, screen recordings) are drawn once and held,
+    // unless a cue could start or end partway through the hold
+    let runs = if playback.subtitles.is_empty() {
+        frame_runs(frames_text)
+    } else {
+        (0..frames_text.len()).map(|index| (index, 1)).collect()
+    };
+
+    let mut terminal = TerminalGuard::new(out, true, !playback.inline)?;
+    let keys = terminal.raw_mode;
+    let out = &mut *terminal.out;
+    clear_screen(out, playback)?;
+
+    if let Some(first_frame) = frames_text.first() {
+        if !count_down(out, first_frame, playback, keys)? {
+            return Ok(());
+        }
+    }
+
+    // held back until now so it lines up with the first frame instead of the countdown
+    if let Some(audio) = audio {
+        audio.sink.play();
+    }
+
+    let mut stats = PlaybackStats::new();
+    let mut paused = false;
+    let mut play = 0;
+    'playback: while plays.map_or(true, |plays| play < plays) && !interrupted() {
+        if play > 0 {
+            if let Some(audio) = audio {
+                audio.restart();
+            }
+        }
+
+        let mut start = Instant::now();
+
+        let mut r = 0;
+        let mut shown = 0; // frames covered by the runs drawn so far
+        while r < runs.len() && !interrupted() {
+            let (index, count) = runs[r];
+            show_frame(out, frames_text, index, diff_base(frames_text, previous, index, playback), playback)?;
+            previous = Some(index);
+            r += 1;
+            shown += count;
+
+            // each frame has its own deadline on the wall clock, so time spent drawing doesn't add up into drift.
+            // When we're already behind the wait below is skipped entirely
+            let mut target = start + delay * shown as u32;
+            let mut remaining = delay * count as u32; // left of the hold when paused
+
+            if playback.stats {
+                // drawing ran past the end of this frame's slot, so it was never on screen as long as it should be
+                stats.frames_shown(count, Instant::now() >= target);
+                draw_stats(out, &frames_text[index], &stats, playback)?;
+            }
+
+            // wait out the frame delay while listening for keys
+            loop {
+                let now = Instant::now();
+                if !paused && now >= target {
+                    break;
+                }
+
+                let timeout = if paused { KEY_POLL_INTERVAL } else { (target - now).min(KEY_POLL_INTERVAL) };
+                if !poll_key(keys, timeout)? {
+                    continue;
+                }
+
+                let key = match read()? {
+                    Event::Key(key) => key,
+                    Event::Resize(_, _) => {
+                        // old rows would linger and wrapped lines garble the frame, so start over on a clean screen
+                        execute!(out, Clear(ClearType::All))?;
+                        show_frame(out, frames_text, runs[r - 1].0, None, playback)?;
+                        continue;
+                    }
+                    _ => continue,
+                };
+
+                match key.code {
+                    KeyCode::Char('q') => break 'playback,
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break 'playback,
+                    KeyCode::Char(' ') => {
+                        paused = !paused;
+
+                        if let Some(audio) = audio {
+                            if paused { audio.sink.pause() } else { audio.sink.play() }
+                        }
+
+                        if paused {
+                            remaining = target.saturating_duration_since(Instant::now());
+                        } else {
+                            // pick the timeline back up from the current frame
+                            target = Instant::now() + remaining;
+                            start = target - delay * shown as u32;
+                            stats.restart_window();
+                        }
+                    }
+                    KeyCode::Right if paused && r < runs.len() => {
+                        let (index, count) = runs[r];
+                        show_frame(out, frames_text, index, diff_base(frames_text, previous, index, playback), playback)?;
+                        previous = Some(index);
+                        r += 1;
+                        shown += count;
+                        remaining = delay * count as u32;
+                    }
+                    _ => {}
+                }
+            }
+
+            if audio.is_some() {
+                // frames we fell behind on get dropped so video keeps up with the audio
+                let behind = ((Instant::now() - start).as_millis() as u64 / frame_delay.max(1)) as usize;
+
+                while r < runs.len() && shown + runs[r].1 <= behind {
+                    shown += runs[r].1;
+                    stats.dropped += runs[r].1;
+                    r += 1;
+                }
+            }
+        }
+
+        play += 1;
+    }
+
+    // q breaks out before the play is counted, so this only holds when playback ran to the end
+    let finished = plays.map_or(false, |plays| play >= plays) && !interrupted();
+
+    // the last frame stays up with the cursor back until any key
+    if playback.freeze && finished {
+        execute!(out, Show)?;
+        wait_for_key(keys)?;
+    }
+
+    Ok(())
+}
+
+// The alternate screen can come up with leftovers from whatever ran before, clearing it first means
+// the first frame paints onto a blank screen. Inline playback leaves the normal screen alone
+fn clear_screen<W: Write>(out: &mut W, playback: &PlaybackOptions) -> crossterm::Result<()> {
+    if !playback.inline {
+        execute!(out, Clear(ClearType::All))?;
+    }
+
+    Ok(())
+}
+
+// --countdown, the first frame with the seconds left over its middle. False when it was quit partway
+fn count_down<W: Write>(out: &mut W, frame_text: &str, playback: &PlaybackOptions, keys: bool) -> crossterm::Result<bool> {
+    let (offset_x, offset_y) = if playback.center { centered_offset(frame_text) } else { (0, 0) };
+    let columns = frame_text.lines().next().map_or(0, |line| parse_ansi_line(line).len());
+    let rows = frame_text.matches('\n').count();
+
+    let mut remaining = playback.countdown;
+    while remaining > 0 {
+        let label = format!(" {} ", remaining);
+        let x = offset_x + (columns.saturating_sub(label.len()) / 2) as u16;
+
+        draw_frame(out, frame_text, None, playback.center)?;
+        queue!(out, MoveTo(x, offset_y + (rows / 2) as u16), Print(LABEL_BACKGROUND), Print(label), Print(RESET_COLOR))?;
+        out.flush()?;
+
+        let next = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < next {
+            if interrupted() {
+                return Ok(false);
+            }
+
+            if !poll_key(keys, next.saturating_duration_since(Instant::now()).min(KEY_POLL_INTERVAL))? {
+                continue;
+            }
+
+            if let Event::Key(key) = read()? {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+
+                if key.code == KeyCode::Char('q') || ctrl_c {
+                    return Ok(false);
+                }
+            }
+        }
+
+        remaining -= 1;
+    }
+
+    Ok(true)
+}
+
+// Waits up to timeout for an event. Without raw mode there are no keys to read, so it only sleeps
+fn poll_key(keys: bool, timeout: Duration) -> crossterm::Result<bool> {
+    if !keys {
+        std::thread::sleep(timeout);
+        return Ok(false);
+    }
+
+    poll(timeout)
+}
+
+// Whether q or Ctrl-C is waiting among the pending events, doesn't block
+fn quit_pressed(keys: bool) -> crossterm::Result<bool> {
+    while poll_key(keys, Duration::ZERO)? {
+        if let Event::Key(key) = read()? {
+            let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+
+            if key.code == KeyCode::Char('q') || ctrl_c {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+// Nothing to wait for without keys
+fn wait_for_key(keys: bool) -> crossterm::Result<()> {
+    while keys && !interrupted() {
+        if poll(KEY_POLL_INTERVAL)? {
+            if let Event::Key(_) = read()? {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// The first play through while VideoToAscii::spawn is still converting, each frame is drawn as it arrives.
+// A converter that falls behind just holds the current frame, nothing is skipped. Space pauses and q quits,
+// dropping the receiver stops the converter. Returns the frames received and whether the video ran to its end
+pub fn print_frames_streamed<W: Write>(
+    out: &mut W,
+    frames: Receiver<Result<String, opencv::Error>>,
+    playback: &PlaybackOptions,
+) -> Result<(Vec<String>, bool)> {
+    let delay = Duration::from_millis(playback.frame_delay);
+    let mut frames_text: Vec<String> = Vec::new();
+
+    let mut terminal = TerminalGuard::new(out, true, !playback.inline)?;
+    let keys = terminal.raw_mode;
+    let out = &mut *terminal.out;
+    clear_screen(out, playback)?;
+
+    let mut start = Instant::now();
+    let mut stats = PlaybackStats::new();
+    let mut paused = false;
+    let mut quit = false;
+
+    'playback: while !interrupted() {
+        let frame_text = match frames.recv() {
+            Ok(frame_text) => frame_text?,
+            Err(_) => break, // the converter is done
+        };
+
+        // waited on the converter, the timeline picks up from now instead of rushing to catch up
+        let shown = frames_text.len() as u32;
+        let behind = Instant::now() > start + delay * shown;
+        if behind {
+            start = Instant::now() - delay * shown;
+        }
+
+        let index = frames_text.len();
+        let previous = match frames_text.last() {
+            Some(previous) if playback.diff && same_subtitle(playback, index - 1, index) => Some(previous.as_str()),
+            _ => None,
+        };
+
+        draw_frame(out, &frame_text, previous, playback.center)?;
+        draw_subtitle(out, &frame_text, index, playback)?;
+
+        if playback.stats {
+            stats.frames_shown(1, behind);
+            draw_stats(out, &frame_text, &stats, playback)?;
+        }
+
+        // the countdown runs over the first frame while the converter keeps going, the timeline starts after it
+        if index == 0 && playback.countdown > 0 {
+            if !count_down(out, &frame_text, playback, keys)? {
+                quit = true;
+                frames_text.push(frame_text);
+                break 'playback;
+            }
+
+            start = Instant::now();
+        }
+
+        frames_text.push(frame_text);
+
+        let mut target = start + delay * frames_text.len() as u32;
+        let mut remaining = delay;
+
+        loop {
+            let now = Instant::now();
+            if !paused && now >= target {
+                break;
+            }
+
+            let timeout = if paused { KEY_POLL_INTERVAL } else { (target - now).min(KEY_POLL_INTERVAL) };
+            if !poll_key(keys, timeout)? {
+                continue;
+            }
+
+            let key = match read()? {
+                Event::Key(key) => key,
+                Event::Resize(_, _) => {
+                    execute!(out, Clear(ClearType::All))?;
+                    draw_frame(out, &frames_text[frames_text.len() - 1], None, playback.center)?;
+                    draw_subtitle(out, &frames_text[frames_text.len() - 1], frames_text.len() - 1, playback)?;
+                    continue;
+                }
+                _ => continue,
+            };
+
+            match key.code {
+                KeyCode::Char('q') => {
+                    quit = true;
+                    break 'playback;
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    quit = true;
+                    break 'playback;
+                }
+                KeyCode::Char(' ') => {
+                    paused = !paused;
+
+                    if paused {
+                        remaining = target.saturating_duration_since(Instant::now());
+                    } else {
+                        target = Instant::now() + remaining;
+                        start = target - delay * frames_text.len() as u32;
+                        stats.restart_window();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let finished = !quit && !interrupted();
+
+    if playback.freeze && finished && playback.plays == Some(1) {
+        execute!(out, Show)?;
+        wait_for_key(keys)?;
+    }
+
+    Ok((frames_text, finished))
+}
+
+// Draws a frame during playback, with the --overlay line when it's on
+fn show_frame<W: Write>(
+    out: &mut W,
+    frames_text: &[Rc<String>],
+    index: usize,
+    previous: Option<&str>,
+    playback: &PlaybackOptions,
+) -> crossterm::Result<()> {
+    let frame_text = &frames_text[index];
+    draw_frame(out, frame_text, previous, playback.center)?;
+    draw_subtitle(out, frame_text, index, playback)?;
+
+    if !playback.overlay {
+        return Ok(());
+    }
+
+    let (offset_x, offset_y) = if playback.center { centered_offset(frame_text) } else { (0, 0) };
+    let rows = frame_text.matches('\n').count() as u16;
+
+    // the line under the frame, or the terminal's last line when the frame fills it
+    let last_row = terminal_size().map_or(u16::MAX, |(_, Height(height))| height.saturating_sub(1));
+    let row = (offset_y + rows).min(last_row);
+
+    let millis = index as u64 * playback.frame_delay;
+    let overlay = format!(
+        "frame {}/{}  {:02}:{:02}.{}",
+        index + 1, frames_text.len(), millis / 60_000, millis / 1000 % 60, millis / 100 % 10,
+    );
+
+    queue!(out, MoveTo(offset_x, row), Clear(ClearType::UntilNewLine), Print(overlay))?;
+    out.flush()?;
+
+    Ok(())
+}
+
+// The active cue over the bottom rows of the frame, centered and cut to the frame's width
+fn draw_subtitle<W: Write>(out: &mut W, frame_text: &str, index: usize, playback: &PlaybackOptions) -> crossterm::Result<()> {
+    let subtitle = match active_subtitle(&playback.subtitles, cue_time(playback, index)) {
+        Some(subtitle) => &playback.subtitles[subtitle],
+        None => return Ok(()),
+    };
+
+    let (offset_x, offset_y) = if playback.center { centered_offset(frame_text) } else { (0, 0) };
+    let columns = frame_text.lines().next().map_or(0, |line| parse_ansi_line(line).len());
+    let rows = frame_text.matches('\n').count();
+
+    // a cue taller than the frame keeps its last lines
+    let lines: Vec<&str> = subtitle.text.lines().collect();
+    let lines = &lines[lines.len().saturating_sub(rows)..];
+
+    for (i, line) in lines.iter().enumerate() {
+        let line = if playback.subtitle_background { format!(" {} ", line) } else { line.to_string() };
+        let line: String = line.chars().take(columns).collect();
+
+        let x = offset_x + ((columns - line.chars().count()) / 2) as u16;
+        let y = offset_y + (rows - lines.len() + i) as u16;
+
+        if playback.subtitle_background {
+            queue!(out, MoveTo(x, y), Print(LABEL_BACKGROUND), Print(line), Print(RESET_COLOR))?;
+        } else {
+            queue!(out, MoveTo(x, y), Print(line))?;
+        }
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+// --stats bookkeeping. fps is measured over about a second of playback, late frames finished drawing after
+// their slot was already over and dropped ones were skipped to keep up with the audio
+struct PlaybackStats {
+    window_start: Instant,
+    window_frames: usize,
+    fps: Option<f64>,
+    late: usize,
+    dropped: usize,
+}
+
+impl PlaybackStats {
+    fn new() -> PlaybackStats {
+        PlaybackStats { window_start: Instant::now(), window_frames: 0, fps: None, late: 0, dropped: 0 }
+    }
+
+    // frames is how many frame slots the drawn frame covers, held repeats count for all of theirs
+    fn frames_shown(&mut self, frames: usize, late: bool) {
+        self.window_frames += frames;
+
+        if late {
+            self.late += 1;
+        }
+
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.fps = Some(self.window_frames as f64 / elapsed.as_secs_f64());
+            self.restart_window();
+        }
+    }
+
+    // a pause would drag the measured fps down, so it starts over afterwards
+    fn restart_window(&mut self) {
+        self.window_start = Instant::now();
+        self.window_frames = 0;
+    }
+}
+
+// The --stats footer, on the line under the frame (under the --overlay line when there is one)
+fn draw_stats<W: Write>(out: &mut W, frame_text: &str, stats: &PlaybackStats, playback: &PlaybackOptions) -> crossterm::Result<()> {
+    let (offset_x, offset_y) = if playback.center { centered_offset(frame_text) } else { (0, 0) };
+    let rows = frame_text.matches('\n').count() as u16 + playback.overlay as u16;
+
+    let last_row = terminal_size().map_or(u16::MAX, |(_, Height(height))| height.saturating_sub(1));
+    let row = (offset_y + rows).min(last_row);
+
+    let fps = stats.fps.map_or(String::from("--"), |fps| format!("{:.1}", fps));
+    let footer = format!(
+        "{} fps (target {:.1})  late {}  dropped {}",
+        fps, 1000.0 / playback.frame_delay.max(1) as f64, stats.late, stats.dropped,
+    );
+
+    queue!(out, MoveTo(offset_x, row), Clear(ClearType::UntilNewLine), Print(footer))?;
+    out.flush()?;
+
+    Ok(())
+}
+
+// The frame draw_frame diffs against, None redraws everything
+fn diff_base<'a>(frames_text: &'a [Rc<String>], previous: Option<usize>, index: usize, playback: &PlaybackOptions) -> Option<&'a str> {
+    if !playback.diff {
+        return None;
+    }
+
+    // the lines under a cue that just changed only get cleaned up by a full redraw
+    previous
+        .filter(|&previous| same_subtitle(playback, previous, index))
+        .map(|previous| frames_text[previous].as_str())
+}
+
+fn same_subtitle(playback: &PlaybackOptions, first: usize, second: usize) -> bool {
+    let cue = |index: usize| active_subtitle(&playback.subtitles, cue_time(playback, index));
+    cue(first) == cue(second)
+}
+
+// Where a frame falls in the video in ms, cues keep the video's own timing whatever the --speed
+fn cue_time(playback: &PlaybackOptions, index: usize) -> u64 {
+    (index as f64 * playback.source_delay) as u64
+}
+
+// Shares one allocation between every copy of a frame, static scenes and --bounce repeat a lot of them.
+// Frames are keyed by a hash of their text, a collision just keeps its own copy
+pub fn intern_frames(frames_text: Vec<String>) -> Vec<Rc<String>> {
+    let mut interned: HashMap<u64, Rc<String>> = HashMap::new();
+    let mut frames = Vec::with_capacity(frames_text.len());
+
+    for frame_text in frames_text {
+        let mut hasher = DefaultHasher::new();
+        frame_text.hash(&mut hasher);
+
+        let frame = match interned.get(&hasher.finish()) {
+            Some(frame) if **frame == frame_text => Rc::clone(frame),
+            Some(_) => Rc::new(frame_text),
+            None => {
+                let frame = Rc::new(frame_text);
+                interned.insert(hasher.finish(), Rc::clone(&frame));
+                frame
+            }
+        };
+
+        frames.push(frame);
+    }
+
+    frames
+}
+
+// Runs of identical frames as (first index, length), interned frames compare by pointer
+fn frame_runs(frames_text: &[Rc<String>]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+
+    let mut i = 0;
+    while i < frames_text.len() {
+        match runs.last_mut() {
+            Some((index, count)) if Rc::ptr_eq(&frames_text[*index], &frames_text[i]) => *count += 1,
+            _ => runs.push((i, 1)),
+        }
+        i += 1;
+    }
+
+    runs
+}
+
+// Hides the cursor (and enables raw mode if asked) until dropped, so an error or panic
+// during playback still leaves the terminal usable. The alternate screen gives the shell back as it was.
+// The escapes go through the same writer as the frames, raw mode is skipped when stdout isn't a terminal
+// since there are no keys to read then. Playback writes through out once the guard holds it
+struct TerminalGuard<'a, W: Write> {
+    out: &'a mut W,
+    raw_mode: bool,
+    alternate_screen: bool,
+}
+
+impl<'a, W: Write> TerminalGuard<'a, W> {
+    fn new(out: &'a mut W, raw_mode: bool, alternate_screen: bool) -> crossterm::Result<TerminalGuard<'a, W>> {
+        let raw_mode = raw_mode && stdout().is_terminal();
+
+        if alternate_screen {
+            execute!(out, EnterAlternateScreen)?;
+        }
+
+        if raw_mode {
+            enable_raw_mode()?;
+        }
+
+        execute!(out, Hide)?;
+
+        Ok(TerminalGuard { out, raw_mode, alternate_screen })
+    }
+}
+
+impl<W: Write> Drop for TerminalGuard<'_, W> {
+    fn drop(&mut self) {
+        // nothing useful to do with errors while dropping
+        let _ = execute!(self.out, Show);
+
+        if self.alternate_screen {
+            let _ = execute!(self.out, LeaveAlternateScreen);
+        }
+
+        if self.raw_mode {
+            let _ = disable_raw_mode();
+        }
+    }
+}
+
+// Swallow Ctrl-C so playback can stop on its own and show the cursor again
+pub fn handle_interrupts() {
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst))
+        .expect("Error while setting Ctrl-C handler");
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// Renders every captured frame as soon as it's read, there is no frame count so it runs until the capture stops,
+// Ctrl-C or a limit in live. Returns the recorded frames (with live.record) and how long the session ran
+pub fn play_live(
+    video_capture: &mut videoio::VideoCapture,
+    size_x: i32,
+    size_y: i32,
+    options: &RenderOptions,
+    live: &LiveOptions,
+) -> Result<(Vec<String>, Duration)> {
+    let mut stdout = stdout();
+    let mut frame = Mat::default();
+    let mut previous_text: Option<String> = None;
+    let mut shown = ShownCells::default();
+    let mut recorded = Vec::new();
+    let mut frame_count = 0;
+    let start = Instant::now();
+
+    let mut smoother = FrameSmoother::new(options.smooth);
+
+    // raw mode so q and Ctrl-C come through as keys, same as print_frames
+    let mut terminal = TerminalGuard::new(&mut stdout, true, false)?;
+    let keys = terminal.raw_mode;
+    let stdout = &mut *terminal.out;
+
+    while !interrupted() && !quit_pressed(keys)? {
+        // the session ends on its own once either limit is reached, otherwise it runs until q or Ctrl-C
+        if live.duration.map_or(false, |duration| start.elapsed() >= duration)
+            || live.max_frames.map_or(false, |max_frames| frame_count >= max_frames)
+        {
+            break;
+        }
+
+        if !video_capture.read(&mut frame)? {
+            break;
+        }
+
+        frame_count += 1;
+
+        let resized_frame = smoother.smooth(resize_frame(&frame, size_x, size_y, options)?)?;
+        let frame_text = frame_to_text(&resized_frame, size_x, size_y, options)?;
+
+        if let Some(threshold) = live.motion_threshold {
+            let (cells_x, levels) = cell_luminance(&resized_frame, size_x, size_y, options)?;
+            draw_moving_cells(stdout, &frame_text, cells_x, &levels, threshold, live.center, &mut shown)?;
+        } else {
+            let previous = if live.diff { previous_text.as_deref() } else { None };
+            draw_frame(stdout, &frame_text, previous, live.center)?;
+        }
+
+        if live.record {
+            recorded.push(frame_text.clone());
+        }
+
+        previous_text = Some(frame_text);
+    }
+
+    Ok((recorded, start.elapsed()))
+}
+
+// Frames are written as-is (color escapes included) with the delimiter between them
+pub fn write_frames(path: &str, frames_text: &[String], delimiter: &str) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    for (i, frame_text) in frames_text.iter().enumerate() {
+        if i > 0 {
+            file.write_all(delimiter.as_bytes())?;
+        }
+
+        file.write_all(frame_text.as_bytes())?;
+    }
+
+    file.flush()
+}
+
+// Splits a rendered line back into cells, understands the color escapes frame_to_text emits
+pub fn parse_ansi_line(line: &str) -> Vec<(char, Option<Color>)> {
+    let mut cells = Vec::new();
+    let mut foreground = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+
+            let mut sequence = String::new();
+            for c in chars.by_ref() {
+                if c == 'm' {
+                    break;
+                }
+                sequence.push(c);
+            }
+
+            let params: Vec<u8> = sequence.split(';').filter_map(|param| param.parse().ok()).collect();
+            match params.as_slice() {
+                [38, 2, r, g, b] => foreground = Some(Color { r: *r, g: *g, b: *b }),
+                [38, 5, index] => foreground = Some(ansi256_to_color(*index)),
+                [0] | [] => foreground = None,
+                _ => {}
+            }
+
+            continue;
+        }
+
+        cells.push((ch, foreground));
+    }
+
+    cells
+}
+
+fn push_escaped_html(html: &mut String, symbol: char) {
+    match symbol {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        '\'' => html.push_str("&#39;"),
+        _ => html.push(symbol),
+    }
+}
+
+// One <div><pre> per frame, runs of the same color share a span. Colors only come through with --color
+pub fn write_html(path: &str, frames_text: &[String], frame_delay: u64) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    writeln!(file, "<!DOCTYPE html>")?;
+    writeln!(file, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(file, "<style>")?;
+    writeln!(file, "body {{ background: #000; color: #fff; }}")?;
+    writeln!(file, "pre {{ font-family: monospace; line-height: 1; margin: 0; }}")?;
+    writeln!(file, ".frame {{ display: none; }} .frame.shown {{ display: block; }}")?;
+    writeln!(file, "</style>\n</head>\n<body>")?;
+
+    for (i, frame_text) in frames_text.iter().enumerate() {
+        let mut html = String::new();
+        html.push_str(if i == 0 { "<div class=\"frame shown\"><pre>" } else { "<div class=\"frame\"><pre>" });
+
+        for line in frame_text.lines() {
+            let mut span_color: Option<Color> = None;
+
+            for (symbol, foreground) in parse_ansi_line(line) {
+                let same_color = match (span_color, foreground) {
+                    (Some(a), Some(b)) => a.r == b.r && a.g == b.g && a.b == b.b,
+                    (None, None) => true,
+                    _ => false,
+                };
+
+                if !same_color {
+                    if span_color.is_some() {
+                        html.push_str("</span>");
+                    }
+
+                    if let Some(color) = foreground {
+                        html.push_str(&format!("<span style=\"color:rgb({},{},{})\">", color.r, color.g, color.b));
+                    }
+
+                    span_color = foreground;
+                }
+
+                push_escaped_html(&mut html, symbol);
+            }
+
+            if span_color.is_some() {
+                html.push_str("</span>");
+            }
+
+            html.push('\n');
+        }
+
+        html.push_str("</pre></div>");
+        writeln!(file, "{}", html)?;
+    }
+
+    if frames_text.len() > 1 {
+        writeln!(file, "<script>")?;
+        writeln!(file, "const frames = document.querySelectorAll(\".frame\");")?;
+        writeln!(file, "let current = 0;")?;
+        writeln!(file, "setInterval(() => {{")?;
+        writeln!(file, "    frames[current].classList.remove(\"shown\");")?;
+        writeln!(file, "    current = (current + 1) % frames.length;")?;
+        writeln!(file, "    frames[current].classList.add(\"shown\");")?;
+        writeln!(file, "}}, {});", frame_delay)?;
+        writeln!(file, "</script>")?;
+    }
+
+    writeln!(file, "</body>\n</html>")?;
+
+    file.flush()
+}
+
+fn push_escaped_json(json: &mut String, text: &str) {
+    for symbol in text.chars() {
+        match symbol {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            symbol if (symbol as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", symbol as u32)),
+            _ => json.push(symbol),
+        }
+    }
+}
+
+// asciinema v2: a header line, then one [seconds, "o", data] event per frame that redraws it from the top left
+pub fn write_cast(path: &str, frames_text: &[String], frame_delay: u64) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    // escapes take no space, so only count the visible cells
+    let width = frames_text.first().and_then(|frame_text| frame_text.lines().next()).map_or(0, |line| parse_ansi_line(line).len());
+    let height = frames_text.iter().map(|frame_text| frame_text.matches('\n').count()).max().unwrap_or(0);
+
+    writeln!(file, "{{\"version\": 2, \"width\": {}, \"height\": {}}}", width, height)?;
+
+    for (i, frame_text) in frames_text.iter().enumerate() {
+        // a clear screen first, then every frame just moves home. Players need \r\n, a bare \n doesn't return the carriage
+        let mut data = String::from(if i == 0 { "\x1b[2J\x1b[H" } else { "\x1b[H" });
+
+        // no newline after the last row, it would scroll the frame up by a line
+        for (row, line) in frame_text.lines().enumerate() {
+            if row > 0 && line != RESET_COLOR {
+                data.push_str("\r\n");
+            }
+            data.push_str(line);
+        }
+
+        let mut json = String::new();
+        push_escaped_json(&mut json, &data);
+
+        writeln!(file, "[{:.3}, \"o\", \"{}\"]", (i as u64 * frame_delay) as f64 / 1000.0, json)?;
+    }
+
+    file.flush()
+}
+
+pub fn find_font(font_path: Option<&str>) -> Option<String> {
+    if let Some(font_path) = font_path {
+        return Some(String::from(font_path));
+    }
+
+    GIF_FONT_PATHS
+        .iter()
+        .find(|path| Path::new(path).exists())
+        .map(|path| String::from(*path))
+}
+
+// Draws every frame onto an image with the font, light text on black like a terminal
+pub fn write_gif(path: &str, frames_text: &[String], frame_delay: u64, font_path: &str) -> Result<()> {
+    let font = FontVec::try_from_vec(std::fs::read(font_path)?)?;
+    let scaled_font = font.as_scaled(PxScale::from(GIF_FONT_SIZE));
+
+    let cell_width = scaled_font.h_advance(font.glyph_id('█')).ceil() as usize;
+    let cell_height = scaled_font.height().ceil() as usize;
+
+    // a held frame (timed gifs, --clip-pause, still scenes) is written once with the whole hold as its delay
+    let mut runs: Vec<(&String, u64)> = Vec::new();
+    for frame_text in frames_text {
+        match runs.last_mut() {
+            Some((text, count)) if *text == frame_text => *count += 1,
+            _ => runs.push((frame_text, 1)),
+        }
+    }
+
+    let frames_cells: Vec<Vec<Vec<(char, Option<Color>)>>> = runs
+        .iter()
+        .map(|(frame_text, _)| frame_text.lines().map(parse_ansi_line).collect())
+        .collect();
+
+    let columns = frames_cells.iter().flatten().map(|line| line.len()).max().unwrap_or(0);
+    let rows = frames_cells.iter().map(|lines| lines.len()).max().unwrap_or(0);
+
+    let width = columns * cell_width;
+    let height = rows * cell_height;
+
+    if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+        anyhow::bail!("Can't make a {}x{} gif", width, height);
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    for (lines, (_, count)) in frames_cells.iter().zip(&runs) {
+        let mut pixels = vec![0u8; width * height * 4];
+        for pixel in pixels.chunks_mut(4) {
+            pixel[3] = 255;
+        }
+
+        for (row, cells) in lines.iter().enumerate() {
+            for (column, (symbol, foreground)) in cells.iter().enumerate() {
+                let color = foreground.unwrap_or(Color { r: 255, g: 255, b: 255 });
+
+                let mut glyph = scaled_font.scaled_glyph(*symbol);
+                glyph.position = point(
+                    (column * cell_width) as f32,
+                    (row * cell_height) as f32 + scaled_font.ascent(),
+                );
+
+                if let Some(outlined) = scaled_font.outline_glyph(glyph) {
+                    let bounds = outlined.px_bounds();
+
+                    outlined.draw(|glyph_x, glyph_y, coverage| {
+                        let x = bounds.min.x as i32 + glyph_x as i32;
+                        let y = bounds.min.y as i32 + glyph_y as i32;
+
+                        if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+                            return;
+                        }
+
+                        let i = (y as usize * width + x as usize) * 4;
+                        let blend = |under: u8, over: u8| (under as f32 * (1.0 - coverage) + over as f32 * coverage) as u8;
+
+                        pixels[i] = blend(pixels[i], color.r);
+                        pixels[i + 1] = blend(pixels[i + 1], color.g);
+                        pixels[i + 2] = blend(pixels[i + 2], color.b);
+                    });
+                }
+            }
+        }
+
+        let mut frame = gif::Frame::from_rgba_speed(width as u16, height as u16, &mut pixels, 10);
+        frame.delay = (frame_delay * count / 10).clamp(1, u16::MAX as u64) as u16; // gif delays are in 1/100s
+
+        encoder.write_frame(&frame)?;
+    }
+
+    Ok(())
+}
+
+// Decodes the audio track up front and keeps it paused until playback starts. --start and --end cut the
+// audio the same as the frames so they start and finish together, speed plays it faster or slower like
+// --speed does the frames (the pitch moves with it)
+pub fn load_audio(path: &str, trim: Trim, speed: f64) -> Option<AudioPlayback> {
+    let file = File::open(path).ok()?;
+    let decoded = AudioDecoder::new(BufReader::new(file)).ok()?; // no audio track, play video only
+
+    let start = trim.start.unwrap_or(0.0);
+    let skipped = decoded.skip_duration(Duration::from_secs_f64(start));
+
+    let source: Box<dyn Source<Item = i16> + Send> = match trim.end {
+        Some(end) => Box::new(skipped.take_duration(Duration::from_secs_f64((end - start).max(0.0)))),
+        None => Box::new(skipped),
+    };
+
+    let (stream, handle) = OutputStream::try_default().ok()?;
+    let sink = Sink::try_new(&handle).ok()?;
+
+    // buffered so every --loop or --loop-count repeat can queue the decoded samples again
+    let source = source.speed(speed as f32).buffered();
+
+    sink.pause();
+    sink.append(source.clone());
+
+    Some(AudioPlayback { _stream: stream, sink, source })
+}
+
+// Builder over the whole pipeline for embedding the converter, ex
+// VideoToAscii::new("video.mp4").size(80, 24).load()?.resize()?.convert(|done, total| println!("{}/{}", done, total))?
+pub struct VideoToAscii {
+    path: String,
+    decoder: Decoder,
+    backend: Backend,
+    size_x: i32,
+    size_y: i32,
+    trim: Trim,
+    skip_every: usize,
+    options: RenderOptions,
+    frames: Vec<Mat>,
+    holds: Vec<usize>, // ticks each animation frame is held for, empty for videos
+    truncated: Cell<bool>, // set by load and stream
+}
+
+impl VideoToAscii {
+    pub fn new(path: &str) -> VideoToAscii {
+        VideoToAscii {
+            path: String::from(path),
+            decoder: Decoder::Opencv,
+            backend: Backend::Any,
+            size_x: 120,
+            size_y: 40,
+            trim: Trim { start: None, end: None, max_frames: None },
+            skip_every: 0,
+            options: RenderOptions::default(),
+            frames: Vec::new(),
+            holds: Vec::new(),
+            truncated: Cell::new(false),
+        }
+    }
+
+    pub fn size(mut self, size_x: i32, size_y: i32) -> VideoToAscii {
+        self.size_x = size_x;
+        self.size_y = size_y;
+        self
+    }
+
+    pub fn decoder(mut self, decoder: Decoder) -> VideoToAscii {
+        self.decoder = decoder;
+        self
+    }
+
+    // The OpenCV backend captures are opened with, the parallel decode threads included
+    pub fn backend(mut self, backend: Backend) -> VideoToAscii {
+        self.backend = backend;
+        self
+    }
+
+    pub fn trim(mut self, trim: Trim) -> VideoToAscii {
+        self.trim = trim;
+        self
+    }
+
+    pub fn skip_every(mut self, skip_every: usize) -> VideoToAscii {
+        self.skip_every = skip_every;
+        self
+    }
+
+    pub fn options(mut self, options: RenderOptions) -> VideoToAscii {
+        self.options = options;
+        self
+    }
+
+    pub fn frames(&self) -> &[Mat] {
+        &self.frames
+    }
+
+    // Whether --max-frames stopped the last load or stream with more of the trim left
+    pub fn truncated(&self) -> bool {
+        self.truncated.get()
+    }
+
+    // Decodes the trimmed video into memory, without the frames skip_every leaves out
+    pub fn load(mut self) -> Result<VideoToAscii, opencv::Error> {
+        // animations always come from imreadmulti, their frames are skipped once convert expands the holds
+        if is_animation(&self.path) {
+            let (frames, truncated) = get_animation_frames(&self.path, self.trim)?;
+            (self.frames, self.holds) = frames.into_iter().unzip();
+            self.truncated.set(truncated);
+
+            return Ok(self);
+        }
+
+        // ffmpeg is only for the videos OpenCV can't read
+        let (frames, truncated) = match self.decoder {
+            Decoder::Ffmpeg => (get_ffmpeg_frames(&self.path, self.trim)?, false),
+            Decoder::Opencv => get_video_frames(&self.path, self.trim, self.backend)?,
+        };
+        self.frames = skip_frames(&frames, self.skip_every)?;
+        self.truncated.set(truncated);
+
+        Ok(self)
+    }
+
+    pub fn resize(mut self) -> Result<VideoToAscii, opencv::Error> {
+        let frames = std::mem::take(&mut self.frames);
+        self.frames = resize_frames(frames, self.size_x, self.size_y, &self.options)?;
+
+        Ok(self)
+    }
+
+    // Frames are converted independently on the rayon pool, collect keeps them in order.
+    // progress gets (frames done, total), one call at a time even though the frames finish on several threads
+    // Mat isn't Sync, so the frames are moved onto the pool instead of shared with it
+    pub fn convert<F: FnMut(usize, usize) + Send>(mut self, mut progress: F) -> Result<Vec<String>, opencv::Error> {
+        let frames = std::mem::take(&mut self.frames);
+        let total = frames.len();
+        progress(0, total);
+
+        let progress = Mutex::new((progress, 0)); // the callback and how many frames are done
+
+        let frames_text = frames
+            .into_par_iter()
+            .map(|frame| {
+                let frame_text = frame_to_text(&frame, self.size_x, self.size_y, &self.options);
+
+                let mut progress = progress.lock().expect("Progress callback panicked");
+                progress.1 += 1;
+                (progress.0)(progress.1, total);
+
+                frame_text
+            })
+            .collect::<Result<Vec<_>, opencv::Error>>()?;
+
+        if self.holds.is_empty() {
+            return Ok(frames_text);
+        }
+
+        Ok(expand_holds(frames_text, &self.holds, self.skip_every))
+    }
+
+    // load, resize and convert in one pass over the video so only the text is kept in memory
+    pub fn stream<F: FnMut(usize, usize)>(&self, progress: F) -> Result<Vec<String>, opencv::Error> {
+        let (frames_text, truncated) = stream_frames_text(self, progress)?;
+        self.truncated.set(truncated);
+
+        Ok(frames_text)
+    }
+
+    // Like stream but on a background thread, each frame's text is sent as soon as it's converted for
+    // print_frames_streamed. The channel holds at most capacity frames so decoding can't run ahead of playback
+    // and eat memory, the thread stops after the receiver is dropped
+    pub fn spawn(self, capacity: usize) -> (Receiver<Result<String, opencv::Error>>, JoinHandle<()>) {
+        let (sender, receiver) = sync_channel(capacity);
+
+        let handle = std::thread::spawn(move || {
+            let result = for_each_frame_text(&self, |_, _| {}, |frame_text| sender.send(Ok(frame_text)).is_ok());
+
+            if let Err(e) = result {
+                let _ = sender.send(Err(e)); // nobody to tell if playback already quit
+            }
+        });
+
+        (receiver, handle)
+    }
+
+    pub fn render(frames_text: &[Rc<String>], playback: &PlaybackOptions, audio: Option<&AudioPlayback>) -> crossterm::Result<()> {
+        print_frames(&mut stdout(), frames_text, playback, audio)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Color {
+    pub r: u8,
+    pub b: u8,
+    pub g: u8
+}
+#[derive(Clone)]
+pub struct Shade {
+    pub symbol: String,
+    pub color: Color,
+}
+
+// Seconds into the video to convert, None means from the beginning / to the end
+#[derive(Clone, Copy)]
+pub struct Trim {
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    pub max_frames: Option<usize>, // stop after this many frames from the start even if the end isn't reached
+}
+
+pub struct AudioPlayback {
+    _stream: OutputStream, // has to outlive the sink or the audio stops
+    pub sink: Sink,
+    source: Buffered<Speed<Box<dyn Source<Item = i16> + Send>>>,
+}
+
+impl AudioPlayback {
+    // Drops whatever is left of the last play and starts the track over with the next one
+    fn restart(&self) {
+        self.sink.stop();
+        self.sink.append(self.source.clone());
+    }
+}
+
+#[derive(Clone)]
+pub struct RenderOptions {
+    pub color: bool,
+    pub color_256: bool, // xterm 256 color escapes instead of 24-bit
+    pub shades: Vec<Shade>,
+    pub mode: Mode,
+    pub dither: Dither,
+    pub letterbox: Option<Size>, // box to center the frame in with spaces
+    pub background: bool,
+    pub interpolation: Interpolation,
+    pub threshold: u8,
+    pub gamma: f64, // applied to every channel before shades are picked, 1 leaves pixels alone
+    pub brightness: f64, // added to every channel after gamma, can be negative
+    pub contrast: f64, // multiplier for the distance from mid-gray
+    pub saturation: f64, // multiplier for every pixel's saturation, 0 is grayscale
+    pub hue_shift: f64, // degrees to rotate every pixel's hue by
+    pub crop: Option<Rect>, // region of the source frame to keep, applied before resizing
+    pub sharpen: f64, // strength of the sharpening applied after resizing, 0 is off
+    pub blur: i32, // gaussian blur radius in source pixels applied before resizing, 0 is off
+    pub mirror: bool, // flip left to right, a selfie view for webcams
+    pub antialias: bool, // average several samples per cell when resizing instead of one
+    pub rotation: u16, // degrees clockwise, 90, 180 or 270 turn the frame after cropping, anything else leaves it
+    pub smooth: f64, // share of the running average kept each frame by FrameSmoother, 0 is off
+    pub palette: Vec<Color>, // pixels are snapped to the nearest of these first, empty keeps them as they are
+    pub invert: bool,
+}
+
+impl RenderOptions {
+    pub fn foreground_escape(&self, color: Color) -> String {
+        if self.color_256 {
+            format!("\x1b[38;5;{}m", nearest_ansi256(color))
+        } else {
+            format!("\x1b[38;2;{};{};{}m", color.r, color.g, color.b)
+        }
+    }
+
+    pub fn background_escape(&self, color: Color) -> String {
+        if self.color_256 {
+            format!("\x1b[48;5;{}m", nearest_ansi256(color))
+        } else {
+            format!("\x1b[48;2;{};{};{}m", color.r, color.g, color.b)
+        }
+    }
+
+    // whether tone_curve changes anything
+    pub fn adjusts_tone(&self) -> bool {
+        self.gamma != 1.0 || self.brightness != 0.0 || self.contrast != 1.0
+    }
+
+    // how many source pixels make up one output cell
+    pub fn cell_pixels(&self) -> (i32, i32) {
+        if self.background {
+            (1, 2)
+        } else if self.mode == Mode::Braille {
+            (2, 4)
+        } else {
+            (1, 1)
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            color: false,
+            color_256: false,
+            shades: default_shades(),
+            mode: Mode::Rgb,
+            dither: Dither::None,
+            letterbox: None,
+            background: false,
+            interpolation: Interpolation::Auto,
+            threshold: 128,
+            gamma: 1.0,
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            hue_shift: 0.0,
+            crop: None,
+            sharpen: 0.0,
+            blur: 0,
+            mirror: false,
+            rotation: 0,
+            antialias: false,
+            smooth: 0.0,
+            palette: Vec::new(),
+            invert: false,
+        }
+    }
+}
+
+pub struct PlaybackOptions {
+    pub frame_delay: u64,
+    pub plays: Option<u64>, // how many times to run through the frames, None loops until interrupted
+    pub center: bool,
+    pub diff: bool, // only redraw the lines that changed since the last frame
+    pub overlay: bool, // frame number and timestamp under the frame
+    pub inline: bool, // draw in the normal screen so the last frame stays behind
+    pub freeze: bool, // hold the last frame after the final play until a key is pressed
+    pub subtitles: Vec<Subtitle>, // cues drawn over the bottom of the frame, timed by source_delay
+    pub source_delay: f64, // ms of video each frame covers, frame_delay before --speed scaled it
+    pub subtitle_background: bool, // draw cues on black so they stay readable over bright frames
+    pub stats: bool, // measured fps, target fps and late/dropped frames under the frame
+    pub countdown: u64, // seconds counted down over the first frame before playback starts, 0 skips it
+}
+
+// How play_live draws and when it stops
+pub struct LiveOptions {
+    pub center: bool,
+    pub diff: bool, // only redraw the lines that changed since the last frame
+    pub motion_threshold: Option<u8>, // only redraw cells whose brightness moved more than this
+    pub duration: Option<Duration>, // stop after this long, None runs until interrupted
+    pub max_frames: Option<usize>, // stop after this many frames
+    pub record: bool, // keep every frame's text so the session can be exported afterwards
+}
+
+// One SubRip cue, times are ms into the converted frames
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subtitle {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ColorMode {
+    Auto,
+    /// 24-bit color
+    Truecolor,
+    /// xterm 256 color palette
+    #[value(name = "256")]
+    Ansi256,
+    None,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Fit {
+    /// Largest size that fits inside both the width and the height
+    Contain,
+    /// Fill the width, tall videos can run past the bottom
+    Width,
+    /// Fill the height, wide videos can run past the edge
+    Height,
+}
+
+// OpenCV videoio backends, which ones exist depends on the platform and how OpenCV was built
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    /// Let OpenCV pick
+    Any,
+    Ffmpeg,
+    Gstreamer,
+    /// Microsoft Media Foundation, Windows
+    Msmf,
+    /// DirectShow, Windows capture devices
+    Dshow,
+    /// Video4Linux, Linux capture devices
+    V4l2,
+    /// macOS
+    Avfoundation,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Decoder {
+    /// OpenCV's videoio, whichever backend it was built with
+    Opencv,
+    /// An ffmpeg process piping raw frames, for codecs the OpenCV build can't read (needs ffmpeg and ffprobe)
+    Ffmpeg,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Dither {
+    None,
+    /// Error diffusion, the smoothest gradients but each pixel depends on the ones before it
+    FloydSteinberg,
+    /// A tiled Bayer pattern between the two nearest shades, cheaper and steady from frame to frame
+    Ordered,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Area when shrinking, cubic when enlarging
+    Auto,
+    Nearest,
+    Linear,
+    Cubic,
+    Area,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Nearest shade by RGB distance
+    Rgb,
+    /// Index into the ramp by perceptual brightness
+    Luminance,
+    /// Line art from sobel edges using - | / \
+    Edges,
+    /// 2x4 pixels per cell as braille dots
+    Braille,
+    /// Shade from brightness, colored with the pixel's hue at full brightness (use with --color)
+    Chroma,
+    /// Always █ in the pixel's own color, a plain low-res color display (turns on --color)
+    Pixels,
+    /// 1-bit, --on-char below --threshold and --off-char above it
+    Threshold,
+    /// Eighth blocks ▁▂▃▄▅▆▇█ by brightness, a finer gradient than the five shades
+    Boxfill,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opencv::core::Scalar;
+
+    fn gray(level: u8) -> Color {
+        Color { r: level, g: level, b: level }
+    }
+
+    // Frame of cols x rows pixels all set to one color, mats are BGR
+    fn solid_frame(cols: i32, rows: i32, color: Color) -> Mat {
+        let scalar = Scalar::new(color.b as f64, color.g as f64, color.r as f64, 0.0);
+        Mat::new_rows_cols_with_default(rows, cols, CV_8UC3, scalar).unwrap()
+    }
+
+    // Black on the left to white on the right in even steps, every row the same
+    fn gradient_frame(cols: i32, rows: i32) -> Mat {
+        let mut frame = solid_frame(cols, rows, gray(0));
+
+        for y in 0..rows {
+            for x in 0..cols {
+                let level = (x * 255 / (cols - 1).max(1)) as u8;
+                *frame.at_2d_mut::<Vec3b>(y, x).unwrap() = Vec3b::from([level, level, level]);
+            }
+        }
+
+        frame
+    }
+
+    // Where each symbol of every rendered line sits in the shades, color escapes are skipped
+    fn ramp_positions(frame_text: &str, shades: &[Shade]) -> Vec<Vec<usize>> {
+        frame_text.lines()
+            .filter(|line| !parse_ansi_line(line).is_empty())
+            .map(|line| {
+                parse_ansi_line(line).iter()
+                    .map(|(symbol, _)| {
+                        shades.iter().position(|shade| shade.symbol == symbol.to_string())
+                            .unwrap_or_else(|| panic!("{:?} isn't in the shades", symbol))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Every line starts on the first shade, ends on the last and never steps back in between
+    fn assert_walks_ramp(frame_text: &str, shades: &[Shade]) {
+        for positions in ramp_positions(frame_text, shades) {
+            assert_eq!(positions.first(), Some(&0), "{:?}", positions);
+            assert_eq!(positions.last(), Some(&(shades.len() - 1)), "{:?}", positions);
+            assert!(positions.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", positions);
+        }
+    }
+
+    #[test]
+    fn gradient_walks_the_ramp_in_each_mode() {
+        let frame = gradient_frame(64, 2);
+
+        for mode in [Mode::Rgb, Mode::Luminance, Mode::Chroma] {
+            let mut options = RenderOptions::default();
+            options.mode = mode;
+            assert_walks_ramp(&frame_to_text(&frame, 64, 2, &options).unwrap(), &options.shades);
+
+            // escapes in front of every cell don't change which symbols are picked
+            options.color = true;
+            assert_walks_ramp(&frame_to_text(&frame, 64, 2, &options).unwrap(), &options.shades);
+        }
+
+        let mut options = RenderOptions::default();
+        options.shades = boxfill_shades();
+        options.mode = Mode::Boxfill;
+        assert_walks_ramp(&frame_to_text(&frame, 64, 2, &options).unwrap(), &options.shades);
+    }
+
+    #[test]
+    fn inverted_gradient_walks_the_ramp_backwards() {
+        let frame = gradient_frame(64, 1);
+        let mut options = RenderOptions::default();
+        invert_shades(&mut options.shades);
+
+        let frame_text = frame_to_text(&frame, 64, 1, &options).unwrap();
+        assert!(frame_text.starts_with(' ') && frame_text.trim_end().ends_with('█'));
+        assert_walks_ramp(&frame_text, &options.shades);
+    }
+
+    #[test]
+    fn dithered_gradient_stays_next_to_the_plain_shades() {
+        let frame = gradient_frame(64, 4);
+        let plain = RenderOptions::default();
+        let plain_positions = ramp_positions(&frame_to_text(&frame, 64, 4, &plain).unwrap(), &plain.shades);
+
+        for dither in [Dither::Ordered, Dither::FloydSteinberg] {
+            let mut options = RenderOptions::default();
+            options.dither = dither;
+
+            let positions = ramp_positions(&frame_to_text(&frame, 64, 4, &options).unwrap(), &options.shades);
+
+            // dithering only ever mixes in a neighbouring shade
+            for (row, plain_row) in positions.iter().zip(&plain_positions) {
+                for (&position, &plain_position) in row.iter().zip(plain_row) {
+                    assert!(position.abs_diff(plain_position) <= 1, "{:?} vs {:?}", row, plain_row);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn black_is_full_block() {
+        let options = RenderOptions::default();
+        assert_eq!(color_to_character(gray(0), &options).unwrap(), "█");
+    }
+
+    #[test]
+    fn white_is_space() {
+        let options = RenderOptions::default();
+        assert_eq!(color_to_character(gray(255), &options).unwrap(), " ");
+    }
+
+    #[test]
+    fn mid_gray_is_middle_shade() {
+        let mut options = RenderOptions::default();
+        assert_eq!(color_to_character(gray(128), &options).unwrap(), "▒");
+        // a little brighter than ▒, saturating_sub made the brighter ░ look like an exact match
+        assert_eq!(color_to_character(gray(160), &options).unwrap(), "▒");
+
+        options.mode = Mode::Luminance;
+        assert_eq!(color_to_character(gray(128), &options).unwrap(), "▒");
+    }
+
+    #[test]
+    fn inverted_black_is_space() {
+        let mut options = RenderOptions::default();
+        invert_shades(&mut options.shades);
+
+        assert_eq!(color_to_character(gray(0), &options).unwrap(), " ");
+        assert_eq!(color_to_character(gray(255), &options).unwrap(), "█");
+    }
+
+    #[test]
+    fn two_levels_is_black_and_white() {
+        let mut options = RenderOptions::default();
+        options.shades = levels_to_shades(2).unwrap();
+
+        assert_eq!(color_to_character(gray(100), &options).unwrap(), "█");
+        assert_eq!(color_to_character(gray(160), &options).unwrap(), " ");
+    }
+
+    #[test]
+    fn small_frame_is_padded_to_size() {
+        let options = RenderOptions::default();
+
+        // 4x2 was asked for but the frame only has 2x2 pixels
+        assert_eq!(frame_to_text(&solid_frame(2, 2, gray(0)), 4, 2, &options).unwrap(), " ██ \n ██ \n");
+    }
+
+    #[test]
+    fn repeated_frames_are_grouped() {
+        let frames_text = ["a", "a", "a", "b", "a", "a"].iter().map(|text| text.to_string()).collect();
+        assert_eq!(frame_runs(&intern_frames(frames_text)), vec![(0, 3), (3, 1), (4, 2)]);
+    }
+
+    #[test]
+    fn repeated_frames_share_one_string() {
+        let frames_text = ["a", "b", "a"].iter().map(|text| text.to_string()).collect();
+        let frames = intern_frames(frames_text);
+
+        assert!(Rc::ptr_eq(&frames[0], &frames[2]));
+        assert!(!Rc::ptr_eq(&frames[0], &frames[1]));
+        assert_eq!(frames[1].as_str(), "b");
+    }
+
+    #[test]
+    fn holds_expand_after_conversion() {
+        let frames_text = vec![String::from("a"), String::from("b")];
+        assert_eq!(expand_holds(frames_text.clone(), &[3, 2], 0), ["a", "a", "a", "b", "b"]);
+
+        // skip_every counts ticks across the holds, not frames
+        assert_eq!(expand_holds(frames_text, &[3, 2], 2), ["a", "a", "b"]);
+    }
+
+    #[test]
+    fn shade_pairs_pick_nearest_level() {
+        let mut options = RenderOptions::default();
+        options.mode = Mode::Luminance;
+        options.shades = pairs_to_shades(&[(0, '#'), (200, '+'), (255, ' ')]).unwrap();
+
+        assert_eq!(options.shades.len(), 256);
+        assert_eq!(color_to_character(gray(99), &options).unwrap(), "#");
+        assert_eq!(color_to_character(gray(110), &options).unwrap(), "+");
+        assert_eq!(color_to_character(gray(240), &options).unwrap(), " ");
+    }
+
+    #[test]
+    fn wide_charset_symbols_are_rejected() {
+        assert!(charset_to_shades(" .:#").is_ok());
+        assert!(charset_to_shades(" .🙂#").is_err());
+        assert!(charset_to_shades(" .\u{301}#").is_err()); // combining accent
+    }
+
+    #[test]
+    fn boxfill_fills_by_brightness() {
+        let mut options = RenderOptions::default();
+        options.mode = Mode::Boxfill;
+        options.shades = boxfill_shades();
+
+        assert_eq!(color_to_character(gray(0), &options).unwrap(), "█");
+        assert_eq!(color_to_character(gray(128), &options).unwrap(), "▄");
+        assert_eq!(color_to_character(gray(255), &options).unwrap(), " ");
+    }
+
+    #[test]
+    fn threshold_splits_at_cutoff() {
+        let mut options = RenderOptions::default();
+        options.mode = Mode::Threshold;
+        options.shades = two_tone_shades('O', '.').unwrap();
+
+        assert_eq!(color_to_character(gray(127), &options).unwrap(), "O");
+        assert_eq!(color_to_character(gray(128), &options).unwrap(), ".");
+
+        options.threshold = 200;
+        assert_eq!(color_to_character(gray(150), &options).unwrap(), "O");
+    }
+
+    #[test]
+    fn draw_frame_moves_to_each_changed_line() {
+        let mut out = Vec::new();
+        draw_frame(&mut out, "ab\ncd\n", None, false).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b[1;1Hab\x1b[2;1Hcd");
+
+        let mut out = Vec::new();
+        draw_frame(&mut out, "ab\ncd\n", Some("ab\nxx\n"), false).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b[2;1Hcd");
+    }
+
+    #[test]
+    fn only_cells_past_the_motion_threshold_are_redrawn() {
+        assert_eq!(line_cells("\x1b[38;2;1;2;3ma\x1b[38;2;4;5;6mb\x1b[0m"), ["\x1b[38;2;1;2;3ma", "\x1b[38;2;4;5;6mb"]);
+
+        let mut shown = ShownCells::default();
+
+        let mut out = Vec::new();
+        draw_moving_cells(&mut out, "ab\ncd\n", 2, &[10, 10, 10, 10], 8, false, &mut shown).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b[1;1Hab\x1b[2;1Hcd");
+
+        // the first cell moved by 5 so it's held, the last one moved by 20
+        let mut out = Vec::new();
+        draw_moving_cells(&mut out, "xb\ncy\n", 2, &[15, 10, 10, 30], 8, false, &mut shown).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b[2;2Hy");
+    }
+
+    #[test]
+    fn subtitle_cues_are_parsed_and_looked_up() {
+        let subtitles = parse_subtitles("\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Hello</i>\r\nthere\r\n\r\n2\n00:01:00.000 --> 00:01:01,000\nBye\n").unwrap();
+        assert_eq!(subtitles, [
+            Subtitle { start: 1000, end: 2500, text: String::from("Hello\nthere") },
+            Subtitle { start: 60_000, end: 61_000, text: String::from("Bye") },
+        ]);
+
+        assert_eq!(active_subtitle(&subtitles, 999), None);
+        assert_eq!(active_subtitle(&subtitles, 2499), Some(0));
+        assert_eq!(active_subtitle(&subtitles, 2500), None);
+        assert_eq!(active_subtitle(&subtitles, 60_000), Some(1));
+    }
+
+    #[test]
+    fn ordered_dither_mixes_the_two_nearest_shades() {
+        let levels = [0.0, 100.0, 200.0];
+
+        // a quarter of the way from 100 to 200 only the lowest quarter of the pattern moves up
+        assert_eq!(ordered_shade(125.0, bayer_threshold(0, 0), &levels), 2);
+        assert_eq!(ordered_shade(125.0, bayer_threshold(1, 0), &levels), 1);
+
+        let moved_up = (0..4)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .filter(|&(x, y)| ordered_shade(125.0, bayer_threshold(x, y), &levels) == 2)
+            .count();
+        assert_eq!(moved_up, 4);
+
+        // exactly on a level, or past the ends of the ramp, there's nothing to mix
+        assert_eq!(ordered_shade(100.0, bayer_threshold(0, 0), &levels), 1);
+        assert_eq!(ordered_shade(250.0, bayer_threshold(3, 3), &levels), 2);
+    }
+
+    #[test]
+    fn gamma_brightens_shadows() {
+        let mut options = RenderOptions::default();
+        assert_eq!(tone_curve(64, &options), 64);
+
+        options.gamma = 2.0;
+        assert_eq!(tone_curve(64, &options), 128);
+        assert_eq!(tone_curve(0, &options), 0);
+        assert_eq!(tone_curve(255, &options), 255);
+    }
+
+    #[test]
+    fn contrast_and_brightness_are_clamped() {
+        let mut options = RenderOptions::default();
+        options.contrast = 2.0;
+
+        assert_eq!(tone_curve(128, &options), 128);
+        assert_eq!(tone_curve(192, &options), 255);
+        assert_eq!(tone_curve(32, &options), 0);
+
+        options.contrast = 1.0;
+        options.brightness = -50.0;
+        assert_eq!(tone_curve(100, &options), 50);
+        assert_eq!(tone_curve(20, &options), 0);
+    }
+
+    #[test]
+    fn skip_zero_keeps_every_frame() {
+        let frames: Vec<Mat> = (0..5).map(|i| solid_frame(1, 1, gray(i))).collect();
+        assert_eq!(skip_frames(&frames, 0).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn skip_two_keeps_every_other_frame() {
+        // each frame's gray level is its index
+        let frames: Vec<Mat> = (0..5).map(|i| solid_frame(1, 1, gray(i))).collect();
+        let kept = skip_frames(&frames, 2).unwrap();
+
+        let indices: Vec<u8> = kept.iter().map(|frame| frame.at_2d::<Vec3b>(0, 0).unwrap()[0]).collect();
+        assert_eq!(indices, vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn mirror_flips_left_to_right() {
+        let mut frame = solid_frame(2, 1, gray(0));
+        *frame.at_2d_mut::<Vec3b>(0, 1).unwrap() = Vec3b::from([255, 255, 255]);
+
+        let mut options = RenderOptions::default();
+        assert_eq!(frame_to_text(&resize_frame(&frame, 2, 1, &options).unwrap(), 2, 1, &options).unwrap(), "█ \n");
+
+        options.mirror = true;
+        assert_eq!(frame_to_text(&resize_frame(&frame, 2, 1, &options).unwrap(), 2, 1, &options).unwrap(), " █\n");
+    }
+
+    #[test]
+    fn rotation_turns_clockwise() {
+        let mut frame = solid_frame(2, 1, gray(0));
+        *frame.at_2d_mut::<Vec3b>(0, 1).unwrap() = Vec3b::from([255, 255, 255]);
+
+        // black on the left ends up on top after a quarter turn clockwise
+        let mut options = RenderOptions::default();
+        options.rotation = 90;
+        assert_eq!(frame_to_text(&resize_frame(&frame, 1, 2, &options).unwrap(), 1, 2, &options).unwrap(), "█\n \n");
+
+        options.rotation = 270;
+        assert_eq!(frame_to_text(&resize_frame(&frame, 1, 2, &options).unwrap(), 1, 2, &options).unwrap(), " \n█\n");
+    }
+
+    #[test]
+    fn fourcc_reads_lowest_byte_first() {
+        assert_eq!(fourcc_to_string(i32::from_le_bytes(*b"avc1")), "avc1");
+        assert_eq!(fourcc_to_string(i32::from_le_bytes(*b"FMP4")), "FMP4");
+        assert_eq!(fourcc_to_string(0), "unknown");
+    }
+
+    #[test]
+    fn antialiasing_averages_each_cell() {
+        // a one pixel checkerboard, nearest neighbour lands on a single black or white pixel per cell
+        let mut frame = solid_frame(8, 8, gray(0));
+        for y in 0..8 {
+            for x in 0..8 {
+                if (x + y) % 2 == 1 {
+                    *frame.at_2d_mut::<Vec3b>(y, x).unwrap() = Vec3b::from([255, 255, 255]);
+                }
+            }
+        }
+
+        let mut options = RenderOptions::default();
+        options.interpolation = Interpolation::Nearest;
+
+        let level = |options: &RenderOptions| resize_frame(&frame, 2, 2, options).unwrap().at_2d::<Vec3b>(0, 0).unwrap()[0];
+        assert!(level(&options) == 0 || level(&options) == 255);
+
+        options.antialias = true;
+        assert!((127..=128).contains(&level(&options)));
+    }
+
+    #[test]
+    fn solid_frame_to_text() {
+        let options = RenderOptions::default();
+
+        assert_eq!(frame_to_text(&solid_frame(3, 2, gray(0)), 3, 2, &options).unwrap(), "███\n███\n");
+        assert_eq!(frame_to_text(&solid_frame(3, 2, gray(255)), 3, 2, &options).unwrap(), "   \n   \n");
+    }
+
+    #[test]
+    fn convert_keeps_frame_order() {
+        let converter = VideoToAscii {
+            frames: vec![solid_frame(3, 2, gray(0)), solid_frame(3, 2, gray(255)), solid_frame(3, 2, gray(0))],
+            ..VideoToAscii::new("unused.mp4").size(3, 2)
+        };
+
+        let mut calls = Vec::new();
+        let frames_text = converter.convert(|done, total| calls.push((done, total))).unwrap();
+
+        assert_eq!(frames_text, ["███\n███\n", "   \n   \n", "███\n███\n"]);
+        assert_eq!(calls, [(0, 3), (1, 3), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn row_luminance_matches_scalar() {
+        let mut frame = solid_frame(7, 1, gray(0));
+
+        let mut x = 0;
+        while x < 7 {
+            *frame.at_2d_mut::<Vec3b>(0, x).unwrap() = Vec3b::from([x as u8 * 40, 255 - x as u8 * 30, x as u8 * 17]);
+            x += 1;
+        }
+
+        let luminances = row_luminance(&frame, 0, 7).unwrap();
+        assert_eq!(luminances.len(), 7);
+
+        let mut x = 0;
+        while x < 7 {
+            let pixel = *frame.at_2d::<Vec3b>(0, x).unwrap();
+            assert_eq!(luminances[x as usize], luminance(Color { b: pixel[0], g: pixel[1], r: pixel[2] }));
+            x += 1;
+        }
+    }
+
+    #[test]
+    fn row_luminance_reads_rois() {
+        let mut frame = solid_frame(8, 2, gray(0));
+
+        let mut x = 0;
+        while x < 8 {
+            *frame.at_2d_mut::<Vec3b>(1, x).unwrap() = Vec3b::from([x as u8 * 30, 255 - x as u8 * 30, x as u8 * 17]);
+            x += 1;
+        }
+
+        let roi = Mat::roi(&frame, Rect::new(2, 0, 5, 2)).unwrap();
+        assert!(!roi.is_continuous());
+
+        assert_eq!(row_luminance(&roi, 1, 5).unwrap(), &row_luminance(&frame, 1, 8).unwrap()[2..7]);
+    }
+
+    #[test]
+    fn smoothing_blends_with_previous_frame() {
+        let mut smoother = FrameSmoother::new(0.5);
+
+        let first = smoother.smooth(solid_frame(2, 1, gray(0))).unwrap();
+        assert_eq!(*first.at_2d::<Vec3b>(0, 0).unwrap(), Vec3b::from([0, 0, 0]));
+
+        let second = smoother.smooth(solid_frame(2, 1, gray(200))).unwrap();
+        assert_eq!(*second.at_2d::<Vec3b>(0, 1).unwrap(), Vec3b::from([100, 100, 100]));
+    }
+
+    #[test]
+    fn pixels_snap_to_nearest_palette_color() {
+        let palette = vec![Color { r: 0, g: 0, b: 0 }, Color { r: 255, g: 0, b: 0 }];
+        let frame = solid_frame(1, 1, Color { r: 200, g: 30, b: 40 });
+
+        let snapped_frame = snap_to_palette(&frame, &palette).unwrap();
+        assert_eq!(*snapped_frame.at_2d::<Vec3b>(0, 0).unwrap(), Vec3b::from([0, 0, 255]));
+
+        assert!(parse_palette_color("#ff8000").is_some());
+        assert!(parse_palette_color("12, 34, 56").is_some());
+        assert!(parse_palette_color("#ff80").is_none());
+    }
+
+    #[test]
+    fn hue_shift_wraps_and_saturation_zero_is_gray() {
+        let mut options = RenderOptions::default();
+        let red = solid_frame(1, 1, Color { r: 255, g: 0, b: 0 });
+
+        // red is hue 0, a full turn backwards lands on red again
+        options.hue_shift = -360.0;
+        let pixel = *adjust_color(&red, &options).unwrap().at_2d::<Vec3b>(0, 0).unwrap();
+        assert_eq!(pixel, Vec3b::from([0, 0, 255]));
+
+        options.hue_shift = 0.0;
+        options.saturation = 0.0;
+        let pixel = *adjust_color(&red, &options).unwrap().at_2d::<Vec3b>(0, 0).unwrap();
+        assert_eq!(pixel[0], pixel[1]);
+        assert_eq!(pixel[1], pixel[2]);
+    }
+
+    #[test]
+    fn print_frames_writes_the_terminal_escapes_to_out() {
+        let frames_text = vec![Rc::new("ab\n".to_string()), Rc::new("cd\n".to_string())];
+        let playback = PlaybackOptions {
+            frame_delay: 1,
+            plays: Some(1),
+            center: false,
+            diff: false,
+            overlay: false,
+            inline: false,
+            freeze: false,
+            subtitles: Vec::new(),
+            source_delay: 1.0,
+            subtitle_background: false,
+            stats: false,
+            countdown: 0,
+        };
+
+        let mut out: Vec<u8> = Vec::new();
+        print_frames(&mut out, &frames_text, &playback, None).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        // the alternate screen and cursor come and go in the buffer, around the frames
+        assert!(out.starts_with("\x1b[?1049h\x1b[?25l"));
+        assert!(out.contains("ab") && out.contains("cd"));
+        assert!(out.ends_with("\x1b[?25h\x1b[?1049l"));
+    }
+}
@@ -1,284 +1,1697 @@
 use anyhow::Result; // Automatically handle the error types
-use indicatif::ProgressBar;
+use indicatif::{ProgressBar, ProgressStyle};
 use terminal_size::{terminal_size, Width, Height};
 
-use std::io::{stdout, Write};
-use std::thread::sleep;
-use std::time::Duration;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, IsTerminal, Write};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
-
-use crossterm::{
-    execute, queue,
-    style::{Print},
-    cursor::{Show, Hide, MoveTo},
-};
+use anyhow::Context;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
 
 use opencv::{
     prelude::*,
-    videoio,
-    core::{Size, Vec3b},
-    imgproc::{resize, INTER_LINEAR}, // optflow::ST_BILINEAR
+    core::{Rect, Size},
 };
 
-const SKIP_EVERY: usize = 0; // 0 for no skipping, 2 for skipping every other frame, etc.
+use video_converter::{
+    animation_delays, boxfill_shades, charset_to_shades, default_shades, detect_color_mode, ffprobe, find_font,
+    first_frame_size, fit_size, frame_to_text, get_preview_frame, handle_interrupts, intern_frames, invert_shades,
+    is_animation, is_image_path, levels_to_shades, load_audio, load_image, load_palette, load_subtitles, native_fps,
+    no_color, open_device, open_video, pairs_to_shades, play_live, print_frames_streamed, resize_frames,
+    two_tone_shades, video_info, write_cast, write_frames, write_gif, write_html, Backend, ColorMode, Decoder, Dither,
+    Fit, Interpolation, LiveOptions, Mode, PlaybackOptions, RenderOptions, Trim, VideoToAscii, ANIMATION_FPS,
+    DEFAULT_FPS,
+};
 
+const MIN_FRAME_DELAY: u64 = 1; // ms
+const BACKGROUND_FRAMES: usize = 64; // how far background conversion can get ahead of playback
+const CONFIG_FILE: &str = "videotoascii.toml";
 
-fn get_video_frames(path: &str) -> Result<Vec<Mat>, opencv::Error> {
-    let mut video_capture = videoio::VideoCapture::from_file(path, videoio::CAP_ANY)?;
+// 1 is any other error and clap already exits with 2 for bad arguments
+const FILE_NOT_FOUND_EXIT_CODE: u8 = 3;
+const UNDECODABLE_EXIT_CODE: u8 = 4;
+const TERMINAL_TOO_SMALL_EXIT_CODE: u8 = 5;
 
-    if !video_capture.is_opened()? {
-        return Err(opencv::Error::new(opencv::core::StsError, "Failed to open video file"));
-    } 
+// Fills the terminal while keeping the source's shape
+fn autosize(source_size: Size, char_aspect: f64, fit: Fit) -> Option<(i32, i32)> {
+    let (Width(width), Height(height)) = terminal_size()?;
 
-    let mut frames = Vec::new();
-    let mut frame = Mat::default();
+    Some(fit_size(source_size, width as i32, height as i32, char_aspect, fit))
+}
+
+// Works out the size frames get converted at and, with --keep-aspect, the box they're letterboxed into
+fn frame_layout(source_size: Option<Size>, args: &Args, options: &RenderOptions) -> (i32, i32, Option<Size>) {
+    let mut size_x = args.size_x;
+    let mut size_y = args.size_y;
+
+    // a cropped video's shape is the crop's
+    let source_size = match (source_size, options.crop) {
+        (Some(_), Some(crop)) => crop.size(),
+        (Some(source_size), None) => source_size,
+        (None, _) => return (size_x, size_y, None),
+    };
+
+    // and a quarter turn swaps its sides
+    let source_size = if options.rotation % 180 == 90 {
+        Size::new(source_size.height, source_size.width)
+    } else {
+        source_size
+    };
+
+    if args.autosize {
+        if let Some(size) = autosize(source_size, args.char_aspect, args.fit) {
+            (size_x, size_y) = size;
+        }
+    }
 
-    while video_capture.read(&mut frame)? {
-        frames.push(frame.clone());
+    if args.keep_aspect {
+        let (fit_x, fit_y) = fit_size(source_size, size_x, size_y, args.char_aspect, Fit::Contain);
+        return (fit_x, fit_y, Some(Size::new(size_x, size_y)));
     }
 
-    Ok(frames)
+    (size_x, size_y, None)
 }
 
-fn skip_frames(frames: &Vec<Mat>, skip_every: usize) -> Result<Vec<Mat>, opencv::Error> {
-    if skip_every == 0 {
-        return Ok(frames.clone()); // if skip_every is 0, skip no frames
+fn new_progress_bar(quiet: bool) -> ProgressBar {
+    if quiet {
+        return ProgressBar::hidden();
     }
 
-    let mut new_frames = Vec::new();
+    same_line_print("Converting frames to text: ");
+    ProgressBar::new(0)
+}
 
-    let mut i = 0;
-    while i < frames.len() {
-        if i % skip_every != 0 {
-            new_frames.push(frames[i].clone());
-        }
-        i += 1;
+// Percentage, ETA and frames/s when the frame count is known, otherwise a spinner with a running count
+fn style_progress_bar(progress_bar: &ProgressBar, frame_count: u64) {
+    if frame_count == 0 {
+        progress_bar.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner} {pos} frames [{elapsed_precise}] {per_sec}"),
+        );
+        progress_bar.enable_steady_tick(100);
+        return;
     }
 
-    Ok(new_frames)
+    progress_bar.set_length(frame_count);
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {wide_bar} {pos}/{len} {percent}% ETA {eta} ({per_sec})"),
+    );
+}
+
+// The converter's (done, total) callback driving the progress bar, styled on the first call once the total is known
+fn track_progress(progress_bar: &ProgressBar) -> impl FnMut(usize, usize) + Send + '_ {
+    let mut styled = false;
+
+    move |done, total| {
+        if !styled {
+            style_progress_bar(progress_bar, total as u64);
+            styled = true;
+        }
+
+        progress_bar.set_position(done as u64);
+    }
 }
 
-fn resize_frames(frames: Vec<Mat>, size_x: i32, size_y: i32) -> Result<Vec<Mat>, opencv::Error> {
-    let mut resized_frames = Vec::new();
-    let target_size = Size::new(size_x, size_y);
+// Cache files are keyed by the video (path, size, mtime), the flags it was converted with and the files they name
+fn cache_path(video_path: &str, args: &Args) -> Option<PathBuf> {
+    // piped video lands in a new temp file every run, a key made from it could never be hit again
+    if args.stdin {
+        return None;
+    }
 
+    let metadata = std::fs::metadata(video_path).ok()?;
+    let mut hasher = DefaultHasher::new();
 
-    let mut i = 0;
-    while i < frames.len() {
-        let mut resized_frame = Mat::default();
+    std::fs::canonicalize(video_path).ok()?.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    metadata.modified().ok()?.hash(&mut hasher);
 
-        resize(&frames[i], &mut resized_frame, target_size, 0.0, 0.0, INTER_LINEAR)
-            .expect("Error while resizing frame");
+    // any flag can change the output, so all of them go into the key
+    format!("{:?}", args).hash(&mut hasher);
 
-        resized_frames.push(resized_frame);
-        i += 1;
+    // the flags only hold the paths, an edited palette or subtitle file has to change the key too
+    for path in [&args.palette, &args.subs].into_iter().flatten() {
+        std::fs::read(path).ok()?.hash(&mut hasher);
     }
 
-    Ok(resized_frames)
-}
+    if args.autosize {
+        terminal_size().map(|(Width(width), Height(height))| (width, height)).hash(&mut hasher);
+    }
 
-fn frame_to_text(frame: &Mat, size_x: i32, size_y: i32) -> Result<String, opencv::Error> {
-    let mut frame_text = String::new();
+    Some(dirs::cache_dir()?.join("video_converter").join(format!("{:016x}.frames", hasher.finish())))
+}
 
-    let mut y = 0;
-    while y < size_y {
-        let mut x = 0;
-        while x < size_x {
-            let pixel = frame.at_2d::<Vec3b>(y, x).unwrap();
-            let color = Color {b: pixel[0], g: pixel[1], r: pixel[2]};
+// A "frame_delay frame_count" header, then each frame as its byte length on a line followed by the text
+fn write_cache(path: &Path, frames_text: &[String], frame_delay: u64) -> std::io::Result<()> {
+    if let Some(cache_dir) = path.parent() {
+        std::fs::create_dir_all(cache_dir)?;
+    }
 
-            let closest_char = color_to_character(color)
-                .expect("Error while converting color to character");
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "{} {}", frame_delay, frames_text.len())?;
 
-            frame_text.push_str(&closest_char);
-            x += 1;
-        }
-    
-        frame_text.push_str("\n");
-        y += 1;
+    for frame_text in frames_text {
+        writeln!(file, "{}", frame_text.len())?;
+        file.write_all(frame_text.as_bytes())?;
     }
 
+    file.flush()
+}
 
-    Ok(frame_text)
+// Written next to --output and --cast exports as <path>.json so they describe how to play them back
+#[derive(Serialize, Deserialize)]
+struct ExportInfo {
+    source_width: i32,
+    source_height: i32,
+    size_x: i32,
+    size_y: i32,
+    fps: f64,
+    frame_delay: u64, // ms
+    charset: Option<String>,
+    color: String,
+    mode: String,
+    frame_count: usize,
 }
 
-fn color_to_character(color: Color) -> Result<String, opencv::Error> {
-    let shades = vec![
-        Shade { symbol: "█", color: Color { r: 0, g: 0, b: 0 } },
-        Shade { symbol: "▓", color: Color { r: 51, g: 51, b: 51 } },
-        Shade { symbol: "▒", color: Color { r: 153, g: 153, b: 153 } },
-        Shade { symbol: "░", color: Color { r: 204, g: 204, b: 204 } },
-        Shade { symbol: " ", color: Color { r: 255, g: 255, b: 255 } },
-    ];
+fn write_export_info(export_path: &str, info: &ExportInfo) -> Result<()> {
+    let info_path = format!("{}.json", export_path);
+    let file = BufWriter::new(File::create(&info_path).with_context(|| format!("Couldn't create {}", info_path))?);
+
+    serde_json::to_writer_pretty(file, info)?;
+
+    Ok(())
+}
 
-    // let shades = vec![
-    //     Shade { symbol: "O", color: Color { r: 0, g: 0, b: 0 } },
-    //     Shade { symbol: " ", color: Color { r: 255, g: 255, b: 255 } },
-    // ];
+// Frames written by --output and the frame delay from their sidecar, --fps and --speed stand in without one
+fn read_replay(path: &str, args: &Args) -> Result<(Vec<String>, u64)> {
+    if !Path::new(path).exists() {
+        return Err(Failure::FileNotFound(path.to_string()).into());
+    }
 
-    // let shades = vec![
-    //     Shade { symbol: "█", color: Color { r: 0, g: 0, b: 0 } },
-    //     Shade { symbol: " ", color: Color { r: 255, g: 255, b: 255 } },
-    // ];
+    let data = std::fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path))?;
+    let frames_text: Vec<String> = data.split(args.delimiter.as_str()).map(|frame_text| frame_text.to_string()).collect();
 
-    // match the color to the closest color in the shades vector
-    let mut i = 0;
-    let mut closest_distance: u32 = 195075;
-    let mut character = String::new();
+    let info_path = format!("{}.json", path);
+    let export_info: Option<ExportInfo> = match std::fs::read_to_string(&info_path) {
+        Ok(info) => Some(serde_json::from_str(&info).with_context(|| format!("Couldn't parse {}", info_path))?),
+        Err(_) => None,
+    };
 
-    while i < shades.len() {
-        let shade = &shades[i];
+    let frame_delay = match &export_info {
+        Some(export_info) => export_info.frame_delay as f64 / args.speed,
+        None => 1000.0 / args.fps / args.speed,
+    };
 
-        let distance: u32 = (color.r.saturating_sub(shade.color.r) as u32).pow(2) +
-            (color.g.saturating_sub(shade.color.g) as u32).pow(2) +
-            (color.b.saturating_sub(shade.color.b) as u32).pow(2);
+    if let Some(export_info) = &export_info {
+        check_width(export_info.size_x, None, args)?;
 
-        if distance < closest_distance {
-            closest_distance = distance;
-            character = String::from(shade.symbol);
+        if export_info.frame_count != frames_text.len() && !args.quiet {
+            eprintln!(
+                "Warning: {} says {} frames but {} were found, was it written with a different --delimiter?",
+                info_path, export_info.frame_count, frames_text.len(),
+            );
         }
-
-        i += 1;
     }
 
-    Ok(character)
+    Ok((frames_text, (frame_delay as u64).max(MIN_FRAME_DELAY)))
 }
 
-fn print_frames(frames_text: &[String], frame_delay: u64) -> crossterm::Result<()> {
-    let mut stdout = stdout();
+fn read_cache(path: &Path) -> Option<(Vec<String>, u64)> {
+    let data = std::fs::read_to_string(path).ok()?;
 
-    execute!(stdout, Hide)?;
+    let (header, mut rest) = data.split_once('\n')?;
+    let mut header = header.split(' ');
 
-    for frame_text in frames_text {
-        queue!(
-            stdout,
-            // Clear(ClearType::All),
-            MoveTo(0, 0),
-            Print(frame_text),
-        )?;
+    let frame_delay: u64 = header.next()?.parse().ok()?;
+    let frame_count: usize = header.next()?.parse().ok()?;
+
+    let mut frames_text = Vec::with_capacity(frame_count);
 
-        stdout.flush()?;
-        sleep(Duration::from_millis(frame_delay));
+    for _ in 0..frame_count {
+        let (length, remaining) = rest.split_once('\n')?;
+        let length: usize = length.parse().ok()?;
+
+        frames_text.push(remaining.get(..length)?.to_string());
+        rest = &remaining[length..];
     }
 
-    execute!(stdout, Show)?;
+    Some((frames_text, frame_delay))
+}
+
+// OpenCV can only open paths, so piped video gets written out to a temp file first
+fn buffer_stdin() -> std::io::Result<TempVideo> {
+    let path = std::env::temp_dir().join(format!("video_converter_stdin_{}", std::process::id()));
+    let stdin_video = TempVideo { path };
+
+    let mut file = BufWriter::new(File::create(&stdin_video.path)?);
+    std::io::copy(&mut std::io::stdin().lock(), &mut file)?;
+    file.flush()?;
+
+    Ok(stdin_video)
+}
+
+// --info, laid out so the numbers can go straight into --start, --end and --fps
+fn print_video_info(video_path: &str, decoder: Decoder, backend: Backend) -> Result<(), opencv::Error> {
+    let info = video_info(video_path, decoder, backend)?;
+
+    let unknown = |known: bool, value: String| if known { value } else { String::from("unknown") };
+
+    println!("{}", video_path);
+    println!("  resolution  {}x{}", info.size.width, info.size.height);
+    println!("  fps         {}", unknown(info.fps > 0.0, format!("{:.3}", info.fps)));
+    println!("  frames      {}", unknown(info.frame_count > 0, info.frame_count.to_string()));
+    println!("  duration    {}", unknown(info.duration > 0.0, format_timestamp(info.duration)));
+    println!("  codec       {}", info.codec);
+    println!("  decoder     {}", info.decoder);
 
     Ok(())
 }
 
+// HH:MM:SS.mmm, the same form parse_timestamp reads
+fn format_timestamp(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
 fn same_line_print(text: &str) {
     print!("{}", text);
     std::io::stdout().flush().unwrap();
 }
 
+// Video buffered from stdin, deleted again when dropped
+struct TempVideo {
+    path: PathBuf,
+}
+
+impl Drop for TempVideo {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Lines wider than the terminal wrap and wreck the animation, --strict turns the warning into an error and --quiet drops it
+fn check_width(size_x: i32, letterbox: Option<Size>, args: &Args) -> Result<()> {
+    let width = letterbox.map_or(size_x, |letterbox| letterbox.width);
+
+    let terminal_width = match terminal_size() {
+        Some((Width(terminal_width), _)) => terminal_width as i32,
+        None => return Ok(()), // not a terminal, nothing to wrap
+    };
+
+    if width <= terminal_width {
+        return Ok(());
+    }
+
+    let message = format!(
+        "The output is {} columns wide but the terminal only has {}, lower --columns or use --autosize",
+        width, terminal_width,
+    );
+
+    if args.strict {
+        return Err(Failure::TerminalTooSmall(message).into());
+    }
+
+    if !args.quiet {
+        eprintln!("Warning: {}", message);
+    }
+    Ok(())
+}
+
+// Animations are read with imread since VideoCapture can't always open them
+fn source_frame_size(video_path: &str, decoder: Decoder, backend: Backend) -> Result<Size, opencv::Error> {
+    if is_animation(video_path) {
+        return load_image(video_path)?.size();
+    }
+
+    if decoder == Decoder::Ffmpeg {
+        return Ok(ffprobe(video_path)?.size);
+    }
+
+    open_video(video_path, backend).and_then(|mut video_capture| first_frame_size(&mut video_capture))
+}
+
+// Conversion size, letterbox and frame delay for a clip
+fn clip_layout(video_path: &str, args: &Args, options: &RenderOptions) -> Result<(i32, i32, Option<Size>, u64), opencv::Error> {
+    let needs_source_size = args.autosize || args.keep_aspect;
+
+    let source_size = if needs_source_size {
+        Some(source_frame_size(video_path, args.decoder, args.backend)?)
+    } else {
+        None
+    };
+
+    let (size_x, size_y, letterbox) = frame_layout(source_size, args, options);
+
+    // timed gifs hold each frame for its own delay, see get_animation_frames
+    let fps = if is_animation(video_path) && animation_delays(video_path).is_some() {
+        ANIMATION_FPS
+    } else if args.native_fps && args.decoder == Decoder::Ffmpeg {
+        match ffprobe(video_path)?.fps {
+            fps if fps > 0.0 => fps,
+            _ => DEFAULT_FPS,
+        }
+    } else if args.native_fps {
+        native_fps(video_path, args.backend)?
+    } else {
+        args.fps
+    };
+
+    // each kept frame stands in for skip_every source frames, so it has to stay on screen that much longer
+    let frames_per_kept = args.skip_every.max(1) as f64;
+
+    // speed only scales the delay, floored so very fast playback doesn't turn into a busy loop
+    let frame_delay: u64 = ((1.0 / fps * 1000.0) * frames_per_kept / args.speed).max(MIN_FRAME_DELAY as f64) as u64;
+
+    Ok((size_x, size_y, letterbox, frame_delay))
+}
+
+// Times decoding, resizing and converting separately on the preload path and prints them as a table
+fn run_bench(video_path: &str, trim: Trim, args: &Args, options: &mut RenderOptions) -> Result<(), opencv::Error> {
+    let (size_x, size_y, letterbox, _) = clip_layout(video_path, args, options)?;
+    options.letterbox = letterbox;
+
+    let converter = VideoToAscii::new(video_path)
+        .decoder(args.decoder)
+        .backend(args.backend)
+        .size(size_x, size_y)
+        .trim(trim)
+        .skip_every(args.skip_every)
+        .options(options.clone());
+
+    let now = Instant::now();
+    let converter = converter.load()?;
+    let decode_time = now.elapsed();
+
+    let now = Instant::now();
+    let converter = converter.resize()?;
+    let resize_time = now.elapsed();
+
+    let now = Instant::now();
+    let frame_count = converter.convert(|_, _| {})?.len();
+    let convert_time = now.elapsed();
+
+    println!("{} frames at {}x{}", frame_count, size_x, size_y);
+    println!("{:<10} {:>10} {:>10}", "phase", "ms", "frames/s");
+
+    let phases = [
+        ("decode", decode_time),
+        ("resize", resize_time),
+        ("convert", convert_time),
+        ("total", decode_time + resize_time + convert_time),
+    ];
+
+    for (phase, time) in phases {
+        let frames_per_second = frame_count as f64 / time.as_secs_f64().max(f64::EPSILON);
+        println!("{:<10} {:>10} {:>10.1}", phase, time.as_millis(), frames_per_second);
+    }
+
+    Ok(())
+}
+
+// Reads --config, or videotoascii.toml from the working directory when it exists
+fn load_config(path: Option<&str>) -> Result<Config> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None if Path::new(CONFIG_FILE).exists() => PathBuf::from(CONFIG_FILE),
+        None => return Ok(Config::default()),
+    };
+
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Couldn't read the config {}", path.display()))?;
+
+    toml::from_str(&text).with_context(|| format!("Couldn't parse the config {}", path.display()))
+}
+
+// Config values fill in whatever wasn't given on the command line
+fn apply_config(args: &mut Args, config: Config, matches: &ArgMatches) -> Result<()> {
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+    if !from_cli("fps") {
+        args.fps = config.fps.unwrap_or(args.fps);
+    }
+
+    if !from_cli("speed") {
+        args.speed = config.speed.unwrap_or(args.speed);
+    }
+
+    if !from_cli("size_x") {
+        args.size_x = config.size_x.unwrap_or(args.size_x);
+    }
+
+    if !from_cli("size_y") {
+        args.size_y = config.size_y.unwrap_or(args.size_y);
+    }
+
+    if !from_cli("size") {
+        if let Some(size) = &config.size {
+            args.size = Some(parse_size(size).map_err(anyhow::Error::msg)?);
+        }
+    }
+
+    if !from_cli("char_aspect") {
+        args.char_aspect = config.char_aspect.unwrap_or(args.char_aspect);
+    }
+
+    if !from_cli("threshold") {
+        args.threshold = config.threshold.unwrap_or(args.threshold);
+    }
+
+    if !from_cli("gamma") {
+        args.gamma = config.gamma.unwrap_or(args.gamma);
+    }
+
+    if !from_cli("brightness") {
+        args.brightness = config.brightness.unwrap_or(args.brightness);
+    }
+
+    if !from_cli("contrast") {
+        args.contrast = config.contrast.unwrap_or(args.contrast);
+    }
+
+    if !from_cli("saturation") {
+        args.saturation = config.saturation.unwrap_or(args.saturation);
+    }
+
+    if !from_cli("hue_shift") {
+        args.hue_shift = config.hue_shift.unwrap_or(args.hue_shift);
+    }
+
+    if !from_cli("fit") {
+        if let Some(fit) = &config.fit {
+            args.fit = Fit::from_str(fit, true).map_err(anyhow::Error::msg)?;
+        }
+    }
+
+    if !from_cli("interpolation") {
+        if let Some(interpolation) = &config.interpolation {
+            args.interpolation = Interpolation::from_str(interpolation, true).map_err(anyhow::Error::msg)?;
+        }
+    }
+
+    // options without a default are only set when they were passed
+    if args.color.is_none() {
+        if let Some(color) = &config.color {
+            args.color = Some(ColorMode::from_str(color, true).map_err(anyhow::Error::msg)?);
+        }
+    }
+
+    // dither = true in the config is the same as --dither on its own
+    if args.dither.is_none() {
+        args.dither = match &config.dither {
+            Some(ConfigDither::On(true)) => Some(Dither::FloydSteinberg),
+            Some(ConfigDither::On(false)) | None => None,
+            Some(ConfigDither::Method(method)) => Some(Dither::from_str(method, true).map_err(anyhow::Error::msg)?),
+        };
+    }
+
+    if args.mode.is_none() {
+        if let Some(mode) = &config.mode {
+            args.mode = Some(Mode::from_str(mode, true).map_err(anyhow::Error::msg)?);
+        }
+    }
+
+    if args.charset.is_none() && args.levels.is_none() && args.shade.is_empty() {
+        args.charset = config.charset;
+        args.levels = config.levels;
+
+        for shade in config.shade.unwrap_or_default() {
+            args.shade.push(parse_shade(&shade).map_err(anyhow::Error::msg)?);
+        }
+    }
+
+    // flags can only be switched on from the command line, so the config can switch them on too
+    args.native_fps |= config.native_fps.unwrap_or(false);
+    args.autosize |= config.autosize.unwrap_or(false);
+    args.keep_aspect |= config.keep_aspect.unwrap_or(false);
+    args.bg |= config.bg.unwrap_or(false);
+    args.invert |= config.invert.unwrap_or(false);
+    args.preload |= config.preload.unwrap_or(false);
+    args.audio |= config.audio.unwrap_or(false);
+    args.quiet |= config.quiet.unwrap_or(false);
+    args.no_wait |= config.no_wait.unwrap_or(false);
+    args.center |= config.center.unwrap_or(false);
+    args.diff |= config.diff.unwrap_or(false);
+    args.inline |= config.inline.unwrap_or(false);
+    args.looping |= config.looping.unwrap_or(false);
+
+    // the command line checks these in parse_positive
+    if args.fps <= 0.0 || args.speed <= 0.0 || args.char_aspect <= 0.0 || args.gamma <= 0.0 || args.contrast <= 0.0 {
+        anyhow::bail!("fps, speed, char_aspect, gamma and contrast in the config must be greater than 0");
+    }
+
+    if args.size_x <= 0 || args.size_y <= 0 {
+        anyhow::bail!("size_x and size_y (columns and rows) in the config must be greater than 0");
+    }
+
+    Ok(())
+}
+
+// Errors scripts can tell apart by the exit code, OpenCV errors count as undecodable too
+#[derive(Debug)]
+enum Failure {
+    FileNotFound(String),
+    Undecodable(String),
+    TerminalTooSmall(String),
+}
 
-struct Color {
-    r: u8,
-    b: u8,
-    g: u8
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Failure::FileNotFound(_) => FILE_NOT_FOUND_EXIT_CODE,
+            Failure::Undecodable(_) => UNDECODABLE_EXIT_CODE,
+            Failure::TerminalTooSmall(_) => TERMINAL_TOO_SMALL_EXIT_CODE,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::FileNotFound(path) => write!(f, "File not found: {}", path),
+            Failure::Undecodable(message) => write!(f, "{}", message),
+            Failure::TerminalTooSmall(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+// dither takes true/false like the other flags or a method name
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigDither {
+    On(bool),
+    Method(String),
 }
-struct Shade {
-    symbol: &'static str,
-    color: Color,
+
+// Defaults from videotoascii.toml, keys are the flag names with underscores, ex size_x = 80
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    fps: Option<f64>,
+    native_fps: Option<bool>,
+    speed: Option<f64>,
+    autosize: Option<bool>,
+    fit: Option<String>,
+    interpolation: Option<String>,
+    keep_aspect: Option<bool>,
+    char_aspect: Option<f64>,
+    #[serde(alias = "columns")]
+    size_x: Option<i32>,
+    #[serde(alias = "rows")]
+    size_y: Option<i32>,
+    size: Option<String>,
+    color: Option<String>,
+    bg: Option<bool>,
+    charset: Option<String>,
+    levels: Option<usize>,
+    shade: Option<Vec<String>>,
+    mode: Option<String>,
+    threshold: Option<u8>,
+    gamma: Option<f64>,
+    brightness: Option<f64>,
+    contrast: Option<f64>,
+    saturation: Option<f64>,
+    hue_shift: Option<f64>,
+    invert: Option<bool>,
+    dither: Option<ConfigDither>,
+    preload: Option<bool>,
+    audio: Option<bool>,
+    quiet: Option<bool>,
+    no_wait: Option<bool>,
+    center: Option<bool>,
+    diff: Option<bool>,
+    inline: Option<bool>,
+    #[serde(rename = "loop")]
+    looping: Option<bool>,
 }
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[arg(short, long, required = true, name = "video-path")]
-    path: String,
+    /// One or more videos, several are played back to back as a playlist
+    #[arg(short, long, required_unless_present_any = ["device", "stdin", "replay"], name = "video-path", num_args = 1..)]
+    path: Vec<String>,
+
+    /// Seconds to hold the last frame of a clip before the next one in a playlist starts
+    #[arg(long, default_value_t = 0.0)]
+    clip_pause: f64,
+
+    /// Treat the path as a still image, common image extensions are detected without this
+    #[arg(long, default_value_t = false)]
+    image: bool,
+
+    /// Convert and print only the middle frame with the current settings, then exit
+    #[arg(long, default_value_t = false)]
+    preview: bool,
 
-    #[arg(short, long, default_value_t = 30.0)]
+    /// Like --preview but with a specific frame index
+    #[arg(long)]
+    preview_frame: Option<usize>,
+
+    /// Stop with an error instead of a warning when the output is wider than the terminal
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// Time decoding, resizing and converting the first video separately and print them instead of playing
+    #[arg(long, default_value_t = false)]
+    bench: bool,
+
+    /// Always convert the video instead of reusing frames cached from an earlier run
+    #[arg(long, default_value_t = false)]
+    no_cache: bool,
+
+    /// Read the video from stdin instead of a path, ex ffmpeg ... -f matroska - | video-converter --stdin
+    #[arg(long, default_value_t = false)]
+    stdin: bool,
+
+    /// Config file with default settings, videotoascii.toml in the working directory is used otherwise
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Capture device index to play live instead of a file, ex 0 for the default webcam
+    #[arg(long)]
+    device: Option<i32>,
+
+    #[arg(short, long, default_value_t = DEFAULT_FPS, value_parser = parse_positive)]
     fps: f64,
 
+    /// Play at the frame rate the video reports instead of --fps
+    #[arg(long, default_value_t = false)]
+    native_fps: bool,
+
+    /// Keep only every nth frame, ex 2 keeps every other frame. The frame delay is multiplied
+    /// by n so the video still plays at its real speed, 0 keeps every frame
+    #[arg(long, default_value_t = 0)]
+    skip_every: usize,
+
+    /// Playback speed multiplier, ex 2.0 for double speed or 0.5 for slow motion
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive)]
+    speed: f64,
+
     #[arg(short, long, default_value_t = false)]
     autosize: bool,
 
-    #[arg(long, default_value_t = 120)]
+    /// Which side of the terminal --autosize fills, contain never lets the frame run off screen
+    #[arg(long, value_enum, default_value_t = Fit::Contain)]
+    fit: Fit,
+
+    /// Resampling used when resizing frames
+    #[arg(long, value_enum, default_value_t = Interpolation::Auto)]
+    interpolation: Interpolation,
+
+    /// Only convert this region of the video, in source pixels, ex 160,0,960,720
+    #[arg(long, value_parser = parse_crop)]
+    crop: Option<Rect>,
+
+    /// Sharpen frames after they're shrunk so edges stay crisp, on its own the strength is 1.0
+    #[arg(long, num_args = 0..=1, default_missing_value = "1.0", value_parser = parse_positive)]
+    sharpen: Option<f64>,
+
+    /// Gaussian blur radius in source pixels before resizing, smooths out detail too fine for the grid
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..))]
+    blur: i32,
+
+    /// Print the video's resolution, fps, frame count, duration and codec, then exit without converting
+    #[arg(long, default_value_t = false)]
+    info: bool,
+
+    /// What decodes the video, ffmpeg pipes frames from the ffmpeg program for codecs the OpenCV build can't
+    /// read (--preview and --device still go through OpenCV)
+    #[arg(long, value_enum, default_value_t = Decoder::Opencv)]
+    decoder: Decoder,
+
+    /// The OpenCV backend to open videos and devices with instead of letting OpenCV pick, for machines where its choice
+    /// opens files but reads garbage
+    #[arg(long, value_enum, default_value_t = Backend::Any)]
+    backend: Backend,
+
+    /// Anti-aliasing, each cell averages a 4x4 grid of samples from its patch of the frame instead of one pixel
+    #[arg(long, default_value_t = false)]
+    aa: bool,
+
+    /// Turn every frame clockwise by 90, 180 or 270 degrees, for videos that still play sideways
+    #[arg(long, default_value_t = 0, value_parser = parse_rotation)]
+    rotate: u16,
+
+    /// Flip every frame left to right, a selfie view for --device
+    #[arg(long, default_value_t = false)]
+    mirror: bool,
+
+    /// Blend each frame with the ones before it to calm flicker on grainy footage, on its own the factor is 0.5
+    #[arg(long, num_args = 0..=1, default_missing_value = "0.5", value_parser = parse_fraction)]
+    smooth: Option<f64>,
+
+    /// File of colors (#rrggbb or r,g,b per line) every pixel is snapped to before shades are picked, ex a 16 color theme
+    #[arg(long)]
+    palette: Option<String>,
+
+    /// Keep the video's shape inside --columns by --rows, the leftover space is filled with blanks
+    #[arg(long, default_value_t = false)]
+    keep_aspect: bool,
+
+    /// How many times taller a terminal cell is than it is wide, used by --autosize and --keep-aspect
+    #[arg(long, default_value_t = 2.0, value_parser = parse_positive)]
+    char_aspect: f64,
+
+    /// Width of the output in character cells, --size-x still works
+    #[arg(short = 'c', long = "columns", alias = "size-x", default_value_t = 120, value_parser = clap::value_parser!(i32).range(1..))]
     size_x: i32,
 
-    #[arg(long, default_value_t = 40)]
+    /// Height of the output in character cells, --size-y still works
+    #[arg(short = 'r', long = "rows", alias = "size-y", default_value_t = 40, value_parser = clap::value_parser!(i32).range(1..))]
     size_y: i32,
+
+    /// WxH in cells or percentages of the terminal, ex 80%x60%. Overrides --columns and --rows, --autosize overrides it
+    #[arg(long, value_parser = parse_size)]
+    size: Option<(Extent, Extent)>,
+
+    /// Color output, on its own it means truecolor. auto (the default) picks truecolor, 256 colors or none from
+    /// $COLORTERM and $TERM, and none when the output is piped or $NO_COLOR is set. Exports stay plain unless a mode is given
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "truecolor")]
+    color: Option<ColorMode>,
+
+    /// Shorthand for --color 256, for terminals without 24-bit color
+    #[arg(long, default_value_t = false)]
+    color256: bool,
+
+    /// Color the background too and pack two pixel rows into each line with ▀
+    #[arg(long, default_value_t = false)]
+    bg: bool,
+
+    /// Characters ordered from brightest to darkest, ex " .:-=+*#%@"
+    #[arg(long)]
+    charset: Option<String>,
+
+    /// Number of evenly spaced gray levels in the ramp, ex 2 for plain black and white
+    #[arg(long, conflicts_with = "charset")]
+    levels: Option<usize>,
+
+    /// Brightness and the symbol drawn nearest to it, repeat for each symbol, ex --shade "0:█" --shade "128:▒" --shade "255: "
+    #[arg(long, value_parser = parse_shade, conflicts_with_all = ["charset", "levels"])]
+    shade: Vec<(u8, char)>,
+
+    /// How pixels are matched to shades, defaults to luminance with --charset and rgb otherwise
+    #[arg(long, value_enum)]
+    mode: Option<Mode>,
+
+    /// Saturation multiplier before conversion, 0 is grayscale and above 1 is more vivid
+    #[arg(long, default_value_t = 1.0)]
+    saturation: f64,
+
+    /// Rotate every pixel's hue by this many degrees, wraps around at 360
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    hue_shift: f64,
+
+    /// Shorthand for --mode pixels, every cell is a █ in the pixel's color
+    #[arg(long, default_value_t = false, conflicts_with = "mode")]
+    pixels: bool,
+
+    /// Brightness cutoff (0-255) for the on/off modes like braille
+    #[arg(long, default_value_t = 128)]
+    threshold: u8,
+
+    /// Symbol for pixels darker than --threshold in --mode threshold
+    #[arg(long, default_value_t = '█')]
+    on_char: char,
+
+    /// Symbol for pixels at or above --threshold in --mode threshold
+    #[arg(long, default_value_t = ' ')]
+    off_char: char,
+
+    /// Gamma correction before shades are picked, above 1 brightens dark footage, ex 2.2
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive)]
+    gamma: f64,
+
+    /// Added to every channel before shades are picked, negative darkens, ex 30 or -30
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    brightness: f64,
+
+    /// Stretches (above 1) or flattens (below 1) the distance of every channel from mid-gray
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive)]
+    contrast: f64,
+
+    /// Reverse the shade ramp for terminals with a light background
+    #[arg(long, default_value_t = false)]
+    invert: bool,
+
+    /// Dither between shades for smoother gradients, on its own it means floyd-steinberg
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "floyd-steinberg")]
+    dither: Option<Dither>,
+
+    /// Where in the video to start converting from, seconds or a timestamp like 1:30 or 00:01:30.5
+    #[arg(long, value_parser = parse_timestamp)]
+    start: Option<f64>,
+
+    /// Where in the video to stop converting at, same forms as --start
+    #[arg(long, value_parser = parse_timestamp)]
+    end: Option<f64>,
+
+    /// Stop reading after this many frames (counted from --start), a safety valve for huge videos.
+    /// With --device it's how many frames to capture
+    #[arg(long, visible_alias = "frames")]
+    max_frames: Option<usize>,
+
+    /// With --device, stop capturing after this many seconds
+    #[arg(long, value_parser = parse_positive)]
+    duration: Option<f64>,
+
+    /// Decode the whole video into memory before converting instead of streaming it
+    #[arg(long, default_value_t = false)]
+    preload: bool,
+
+    /// Play the video's audio track alongside the animation
+    #[arg(long, default_value_t = false)]
+    audio: bool,
+
+    /// No progress bar, timings or prompt, playback starts right away
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+
+    /// Center the animation in the terminal instead of drawing it in the top left corner
+    #[arg(long, default_value_t = false)]
+    center: bool,
+
+    /// Only redraw the lines that changed since the previous frame, cuts down flicker on mostly still video
+    #[arg(long, default_value_t = false)]
+    diff: bool,
+
+    /// With --device, only redraw the cells whose brightness moved more than this (0-255) since they were drawn
+    #[arg(long)]
+    motion_threshold: Option<u8>,
+
+    /// Show the frame number and timestamp under the animation, ex frame 120/3000  00:04.0
+    #[arg(long, default_value_t = false)]
+    overlay: bool,
+
+    /// Restart playback from the first frame until Ctrl-C
+    #[arg(long = "loop", default_value_t = false)]
+    looping: bool,
+
+    /// Play the animation this many times, implies --loop
+    #[arg(long)]
+    loop_count: Option<u64>,
+
+    /// Play in the normal screen instead of the alternate one, so the last frame stays in the scrollback
+    #[arg(long, default_value_t = false)]
+    inline: bool,
+
+    /// Keep the last frame on screen after playback until a key is pressed
+    #[arg(long, default_value_t = false)]
+    freeze: bool,
+
+    /// Count down this many seconds over the first frame before playback starts, ex 3
+    #[arg(long, default_value_t = 0)]
+    countdown: u64,
+
+    /// Show the measured fps, the target fps and how many frames came late or were dropped under the animation
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    /// SubRip (.srt) subtitles to show over the bottom of the animation
+    #[arg(long)]
+    subs: Option<String>,
+
+    /// Draw --subs on a black background so they stay readable over bright frames
+    #[arg(long, default_value_t = false)]
+    subs_background: bool,
+
+    /// Start playing as soon as the frames are ready instead of waiting for enter, it's skipped anyway when
+    /// stdin isn't a terminal
+    #[arg(long, default_value_t = false)]
+    no_wait: bool,
+
+    /// Start playing a single long video right away while the rest converts on a background thread, no enter
+    /// prompt or progress bar
+    #[arg(long, default_value_t = false)]
+    progressive: bool,
+
+    /// Play the frames from last to first
+    #[arg(long, default_value_t = false)]
+    reverse: bool,
+
+    /// Play forwards then backwards, repeats like --loop unless --loop-count is given
+    #[arg(long, default_value_t = false)]
+    bounce: bool,
+
+    /// Write the converted frames to a file instead of playing them
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Play frames written by --output instead of converting a video, timing comes from its .json sidecar
+    #[arg(long)]
+    replay: Option<String>,
+
+    /// Write the animation as a web page, use with --color for colored spans
+    #[arg(long)]
+    html: Option<String>,
+
+    /// Write the animation as an asciinema cast, ex for asciinema play or uploading
+    #[arg(long)]
+    cast: Option<String>,
+
+    /// Render the animation to an animated gif
+    #[arg(long)]
+    gif: Option<String>,
+
+    /// Monospace font (ttf/otf) used for --gif, a few common system fonts are tried otherwise
+    #[arg(long)]
+    font: Option<String>,
+
+    /// Separator written between frames in --output, defaults to a form feed
+    #[arg(long, default_value = "\x0c")]
+    delimiter: String,
 }
 
+fn parse_positive(value: &str) -> Result<f64, String> {
+    let number: f64 = value.parse().map_err(|_| format!("`{}` isn't a number", value))?;
 
-fn main() {
-    let mut frames: Vec<Mat> = Vec::new();
+    if !number.is_finite() || number <= 0.0 {
+        return Err(String::from("must be greater than 0"));
+    }
 
-    let args = Args::parse();
-    let video_path = &args.path;
+    Ok(number)
+}
 
-    let frame_delay: u64 = (1.0 / args.fps * 1000.0) as u64; // 60 fps
+// SS, MM:SS or HH:MM:SS into seconds, only the seconds can have a fraction
+fn parse_timestamp(value: &str) -> Result<f64, String> {
+    let parts: Vec<&str> = value.split(':').collect();
 
-    let mut size_x = args.size_x;
-    let mut size_y = args.size_y;
+    if parts.len() > 3 {
+        return Err(format!("`{}` isn't SS, MM:SS or HH:MM:SS", value));
+    }
 
+    let (seconds, units) = parts.split_last().expect("split always gives at least one part");
 
-    if args.autosize {
-        if let Some((Width(width), Height(height))) = terminal_size() {
-            // Calculate size_y based on width and the 4:1 aspect ratio
-            size_y = (width as f64 / 4.0) as i32;
-
-            // Adjust size_x to maintain the 4:1 aspect ratio
-            size_x = size_y * 4;
-
-            // Check if the calculated size exceeds the available height
-            if size_y > height as i32 {
-                // Adjust size_y to fit the available height
-                size_y = height as i32;
-                size_x = size_y * 4;
+    let seconds: f64 = seconds.parse().map_err(|_| format!("`{}` isn't a number of seconds", seconds))?;
+    if !seconds.is_finite() || seconds < 0.0 || (!units.is_empty() && seconds >= 60.0) {
+        return Err(format!("`{}` isn't a valid number of seconds", value));
+    }
+
+    let mut total = 0.0;
+    for (i, unit) in units.iter().enumerate() {
+        let unit: u32 = unit.parse().map_err(|_| format!("`{}` isn't a whole number of hours or minutes", unit))?;
+
+        // the leading unit can run as high as it likes, ex 90:00, minutes after hours can't
+        if i > 0 && unit >= 60 {
+            return Err(format!("`{}` has more than 59 minutes", value));
+        }
+
+        total = total * 60.0 + unit as f64;
+    }
+
+    Ok(total * 60.0 + seconds)
+}
+
+fn parse_rotation(value: &str) -> Result<u16, String> {
+    match value.parse() {
+        Ok(degrees @ (0 | 90 | 180 | 270)) => Ok(degrees),
+        _ => Err(String::from("must be 90, 180 or 270")),
+    }
+}
+
+// Blend factors like --smooth, 1 would freeze on the first frame
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    let number: f64 = value.parse().map_err(|_| format!("`{}` isn't a number", value))?;
+
+    if !(0.0..1.0).contains(&number) {
+        return Err(String::from("must be at least 0 and less than 1"));
+    }
+
+    Ok(number)
+}
+
+// x,y,w,h for --crop
+fn parse_crop(value: &str) -> Result<Rect, String> {
+    let numbers: Vec<i32> = value
+        .split(',')
+        .map(|number| number.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("`{}` isn't x,y,w,h", value))?;
+
+    match numbers.as_slice() {
+        [x, y, width, height] if *width > 0 && *height > 0 => Ok(Rect::new(*x, *y, *width, *height)),
+        [_, _, _, _] => Err(String::from("width and height must be greater than 0")),
+        _ => Err(format!("`{}` isn't x,y,w,h", value)),
+    }
+}
+
+// level:symbol for --shade, levels are 0-255
+fn parse_shade(value: &str) -> Result<(u8, char), String> {
+    let invalid = || format!("`{}` isn't level:symbol with a level from 0 to 255 and one symbol, ex 128:▒", value);
+    let (level, symbol) = value.split_once(':').ok_or_else(invalid)?;
+
+    let level = level.trim().parse().map_err(|_| invalid())?;
+    let mut symbols = symbol.chars();
+
+    match (symbols.next(), symbols.next()) {
+        (Some(symbol), None) => Ok((level, symbol)),
+        _ => Err(invalid()),
+    }
+}
+
+// One side of --size
+#[derive(Debug, Clone, Copy)]
+enum Extent {
+    Cells(i32),
+    Percent(f64),
+}
+
+// WxH for --size, each side is a cell count or a percentage of the terminal
+fn parse_size(value: &str) -> Result<(Extent, Extent), String> {
+    let parse_extent = |side: &str| -> Option<Extent> {
+        let side = side.trim();
+
+        let extent = match side.strip_suffix('%') {
+            Some(percent) => Extent::Percent(percent.parse().ok()?),
+            None => Extent::Cells(side.parse().ok()?),
+        };
+
+        match extent {
+            Extent::Cells(cells) if cells > 0 => Some(extent),
+            Extent::Percent(percent) if percent > 0.0 => Some(extent),
+            _ => None,
+        }
+    };
+
+    let invalid = || format!("`{}` isn't WxH with positive cell counts or percentages, ex 80%x60%", value);
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+
+    match (parse_extent(width), parse_extent(height)) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+// Turns --size into size_x and size_y, percentages are of the terminal at startup
+fn resolve_size(args: &mut Args) -> Result<()> {
+    let (width, height) = match args.size {
+        Some(size) => size,
+        None => return Ok(()),
+    };
+
+    let terminal = terminal_size();
+
+    let resolve = |extent: Extent, terminal_cells: Option<u16>| -> Result<i32> {
+        match extent {
+            Extent::Cells(cells) => Ok(cells),
+            Extent::Percent(percent) => {
+                let terminal_cells = terminal_cells.context("--size with a percentage needs a terminal to measure")?;
+                Ok(((terminal_cells as f64 * percent / 100.0).round() as i32).max(1))
             }
         }
+    };
+
+    args.size_x = resolve(width, terminal.map(|(Width(width), _)| width))?;
+    args.size_y = resolve(height, terminal.map(|(_, Height(height))| height))?;
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e); // alternate format keeps the cause after any context
+
+            let code = match e.downcast_ref::<Failure>() {
+                Some(failure) => failure.exit_code(),
+                None if e.downcast_ref::<opencv::Error>().is_some() => UNDECODABLE_EXIT_CODE,
+                None => 1,
+            };
+
+            ExitCode::from(code)
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let config = load_config(args.config.as_deref())?;
+    apply_config(&mut args, config, &matches)?;
+    resolve_size(&mut args)?;
+
+    // custom charsets are a brightness ramp, so they default to luminance
+    let mode = match args.mode {
+        _ if args.pixels => Mode::Pixels,
+        Some(mode) => mode,
+        None if args.charset.is_some() || !args.shade.is_empty() => Mode::Luminance,
+        None => Mode::Rgb,
+    };
+
+    let mut shades = match (&args.charset, args.levels) {
+        _ if mode == Mode::Threshold => two_tone_shades(args.on_char, args.off_char)?,
+        _ if mode == Mode::Boxfill => boxfill_shades(),
+        _ if !args.shade.is_empty() => pairs_to_shades(&args.shade)?,
+        (Some(charset), _) => charset_to_shades(charset)?,
+        (None, Some(levels)) => levels_to_shades(levels)?,
+        (None, None) => default_shades(),
+    };
+
+    if args.invert {
+        invert_shades(&mut shades); // for light terminal backgrounds
     }
 
+    let requested_color = if args.color256 { Some(ColorMode::Ansi256) } else { args.color };
+    let exporting = args.output.is_some() || args.html.is_some() || args.cast.is_some() || args.gif.is_some();
+
+    let colored_blocks = if args.bg { "--bg" } else { "--mode pixels" };
 
-    match get_video_frames(&video_path) {
-        Ok(result_frames) => {
-            let skipped_frames = skip_frames(&result_frames, SKIP_EVERY)
-                .expect("Error while skipping frames");
-    
-            let resized_frames = resize_frames(skipped_frames, size_x, size_y)
-                .expect("Error while resizing frames");
+    let color_mode = match requested_color {
+        Some(ColorMode::Auto) => detect_color_mode(),
+        Some(color_mode) => color_mode,
+        // blocks without color would be a blank wall, so NO_COLOR needs an explicit --color to go with them
+        None if (mode == Mode::Pixels || args.bg) && no_color() => {
+            anyhow::bail!("{} only draws colored blocks and NO_COLOR is set, pass --color to use it anyway", colored_blocks)
+        }
+        None if mode == Mode::Pixels || args.bg => ColorMode::Truecolor,
+        None if exporting => ColorMode::None, // files don't have a terminal to ask, plain text unless --color says so
+        None => detect_color_mode(),
+    };
+
+    // half blocks are nothing but foreground and background colors
+    if args.bg && color_mode == ColorMode::None {
+        anyhow::bail!("--bg only draws colored blocks, it can't be used with --color none or when --color auto finds no color support");
+    }
 
-            frames = resized_frames;        
+    // the cache key hashes the args, a piped run mustn't pick up frames cached with escapes in them
+    args.color = Some(color_mode);
+
+    let mut render_options = RenderOptions {
+        color: color_mode != ColorMode::None,
+        color_256: color_mode == ColorMode::Ansi256,
+        shades,
+        mode,
+        dither: args.dither.unwrap_or(Dither::None),
+        letterbox: None,
+        background: args.bg,
+        interpolation: args.interpolation,
+        threshold: args.threshold,
+        gamma: args.gamma,
+        brightness: args.brightness,
+        contrast: args.contrast,
+        saturation: args.saturation,
+        hue_shift: args.hue_shift,
+        crop: args.crop,
+        sharpen: args.sharpen.unwrap_or(0.0),
+        blur: args.blur,
+        mirror: args.mirror,
+        rotation: args.rotate,
+        antialias: args.aa,
+        smooth: args.smooth.unwrap_or(0.0),
+        palette: match &args.palette {
+            Some(palette_path) => load_palette(palette_path)?,
+            None => Vec::new(),
         },
-        Err(e) => {
-            println!("Error: {}", e);
+        invert: args.invert,
+    };
+
+    let needs_source_size = args.autosize || args.keep_aspect;
+
+    // exported frames play straight back, no video or OpenCV involved
+    if let Some(replay_path) = &args.replay {
+        let (frames_text, frame_delay) = read_replay(replay_path, &args)?;
+        return play(frames_text, frame_delay, &args, None);
+    }
+
+    if let Some(device) = args.device {
+        handle_interrupts();
+
+        let mut video_capture = open_device(device, args.backend)?;
+        let source_size = if needs_source_size { Some(first_frame_size(&mut video_capture)?) } else { None };
+
+        let (size_x, size_y, letterbox) = frame_layout(source_size, &args, &render_options);
+        render_options.letterbox = letterbox;
+        check_width(size_x, letterbox, &args)?;
+
+        let live = LiveOptions {
+            center: args.center,
+            diff: args.diff,
+            motion_threshold: args.motion_threshold,
+            duration: args.duration.map(Duration::from_secs_f64),
+            max_frames: args.max_frames,
+            record: exporting,
+        };
+
+        let (frames_text, elapsed) = play_live(&mut video_capture, size_x, size_y, &render_options, &live)?;
+
+        if !exporting {
+            return Ok(());
+        }
+
+        if frames_text.is_empty() {
+            return Err(Failure::Undecodable(format!("No frames were captured from device {}", device)).into());
         }
+
+        // exports play back at the rate the camera actually delivered
+        let frame_delay = (elapsed.as_millis() as u64 / frames_text.len() as u64).max(MIN_FRAME_DELAY);
+
+        let export_info = if args.output.is_some() || args.cast.is_some() {
+            let source_size = match source_size {
+                Some(source_size) => source_size,
+                None => first_frame_size(&mut video_capture)?,
+            };
+
+            Some(ExportInfo {
+                source_width: source_size.width,
+                source_height: source_size.height,
+                size_x,
+                size_y,
+                fps: 1000.0 / frame_delay as f64,
+                frame_delay,
+                charset: args.charset.clone(),
+                color: color_mode.to_possible_value().expect("No color mode is skipped").get_name().to_string(),
+                mode: mode.to_possible_value().expect("No mode is skipped").get_name().to_string(),
+                frame_count: frames_text.len(),
+            })
+        } else {
+            None
+        };
+
+        return write_exports(&frames_text, frame_delay, &args, export_info.as_ref());
     }
 
+    let stdin_video = if args.stdin {
+        Some(buffer_stdin().context("Couldn't read the video from stdin")?)
+    } else {
+        None
+    };
+
+    let video_paths: Vec<&str> = match &stdin_video {
+        Some(stdin_video) => vec![stdin_video.path.to_str().expect("Temp dir path isn't valid unicode")],
+        None => args.path.iter().map(|path| path.as_str()).collect(),
+    };
+
+    // OpenCV gives the same error for a missing file and one it can't decode
+    for video_path in &video_paths {
+        if !Path::new(video_path).exists() {
+            return Err(Failure::FileNotFound(video_path.to_string()).into());
+        }
+    }
+
+    if args.info {
+        for video_path in &video_paths {
+            print_video_info(video_path, args.decoder, args.backend)?;
+        }
+
+        return Ok(());
+    }
+
+    // stills, previews, the frame size, frame rate and audio all come from the first clip
+    let video_path = *video_paths.first().expect("A video path is required without --device or --stdin");
+    let trim = Trim { start: args.start, end: args.end, max_frames: args.max_frames };
+
+    let single_frame = if args.image || (is_image_path(video_path) && !is_animation(video_path)) {
+        Some(load_image(video_path)?)
+    } else if args.preview || args.preview_frame.is_some() {
+        Some(get_preview_frame(video_path, args.preview_frame, args.backend)?)
+    } else {
+        None
+    };
+
+    if let Some(frame) = single_frame {
+        // still images and previews are converted once and printed, no animation
+        let (size_x, size_y, letterbox) = frame_layout(Some(frame.size()?), &args, &render_options);
+        render_options.letterbox = letterbox;
+        check_width(size_x, letterbox, &args)?;
+
+        let resized_frames = resize_frames(vec![frame], size_x, size_y, &render_options)?;
+        print!("{}", frame_to_text(&resized_frames[0], size_x, size_y, &render_options)?);
+
+        return Ok(());
+    }
+
+    if args.bench {
+        return Ok(run_bench(video_path, trim, &args, &mut render_options)?);
+    }
 
     let mut frames_text: Vec<String> = Vec::new();
+    // with --progressive a single video can start playing while it's still being converted, anything that needs
+    // every frame up front (exports, playlists, reversing, audio sync, the overlay's frame count) converts first
+    let background = args.progressive && !args.preload && !exporting && video_paths.len() == 1
+        && !args.reverse && !args.bounce && !args.audio && !args.overlay;
 
-    let progress_bar = ProgressBar::new(frames.len() as u64);
-    let now = std::time::Instant::now();
+    if background {
+        let cache_path = if args.no_cache { None } else { cache_path(video_path, &args) };
 
-    same_line_print("Converting frames to text: ");
-    for frame in frames.iter() {
-        let frame_text = frame_to_text(frame, size_x, size_y)
-            .expect("Failed to convert frame");
+        if cache_path.as_ref().map_or(true, |cache_path| !cache_path.exists()) {
+            return play_in_background(video_path, trim, &args, &mut render_options, cache_path);
+        }
+    }
+
+    let mut frame_delay = None;
+    let mut layout = None;
+
+    for clip_path in &video_paths {
+        let cache_path = if args.no_cache { None } else { cache_path(clip_path, &args) };
+
+        let (clip_text, clip_delay) = match cache_path.as_deref().and_then(read_cache) {
+            Some((clip_text, clip_delay)) => {
+                if !args.quiet {
+                    println!("Loaded {} frames from the cache", clip_text.len());
+                }
+                (clip_text, clip_delay)
+            },
+            None => {
+                // every clip is converted at the first one's size so the playlist plays seamlessly
+                if layout.is_none() {
+                    let (size_x, size_y, letterbox, clip_delay) = clip_layout(video_path, &args, &render_options)?;
+
+                    // caught before a long conversion, exported files don't care about the terminal
+                    if !exporting {
+                        check_width(size_x, letterbox, &args)?;
+                    }
+
+                    layout = Some((size_x, size_y, letterbox, clip_delay));
+                }
+
+                let (size_x, size_y, letterbox, clip_delay) = layout.expect("Layout was just worked out");
+                render_options.letterbox = letterbox;
+
+                let now = Instant::now();
+
+                let converter = VideoToAscii::new(clip_path)
+                    .decoder(args.decoder)
+                    .backend(args.backend)
+                    .size(size_x, size_y)
+                    .trim(trim)
+                    .skip_every(args.skip_every)
+                    .options(render_options.clone());
+
+                let progress_bar = new_progress_bar(args.quiet);
+
+                let result = if args.preload {
+                    converter.load().and_then(|converter| {
+                        let truncated = converter.truncated();
+                        Ok((converter.resize()?.convert(track_progress(&progress_bar))?, truncated))
+                    })
+                } else {
+                    converter.stream(track_progress(&progress_bar)).map(|clip_text| (clip_text, converter.truncated()))
+                };
+
+                progress_bar.finish();
+
+                if !args.quiet {
+                    println!(); // off the progress bar's line
+                }
+
+                let (clip_text, truncated) = result?;
+
+                if let Some(max_frames) = args.max_frames.filter(|_| truncated && !args.quiet) {
+                    eprintln!("Warning: Stopped reading after {} frames (--max-frames)", max_frames);
+                }
+
+                // opens fine but reads nothing, a blank animation would look like it worked
+                if clip_text.is_empty() {
+                    return Err(Failure::Undecodable(format!(
+                        "No frames could be decoded from {}, it may be corrupt or use an unsupported codec",
+                        clip_path,
+                    )).into());
+                }
+
+                if !args.quiet {
+                    println!("Time taken to get frames: {}ms", now.elapsed().as_millis());
+                }
+
+                if let Some(cache_path) = &cache_path {
+                    if let Err(e) = write_cache(cache_path, &clip_text, clip_delay) {
+                        if !args.quiet {
+                            eprintln!("Warning: Couldn't write the cache: {}", e);
+                        }
+                    }
+                }
+
+                (clip_text, clip_delay)
+            }
+        };
+
+        // the whole playlist plays at the first clip's frame delay
+        let playlist_delay = *frame_delay.get_or_insert(clip_delay);
+
+        // the last frame of a clip holds for --clip-pause, playback draws the repeats only once
+        if let Some(last_frame) = frames_text.last().cloned() {
+            let pause_frames = (args.clip_pause * 1000.0 / playlist_delay as f64).round() as usize;
+            frames_text.extend(std::iter::repeat(last_frame).take(pause_frames));
+        }
+
+        frames_text.extend(clip_text);
+    }
+
+    let frame_delay = frame_delay.expect("There's at least one clip");
+
+    let export_info = if args.output.is_some() || args.cast.is_some() {
+        // cache hits skip working out the layout
+        let (size_x, size_y, _, _) = match layout {
+            Some(layout) => layout,
+            None => clip_layout(video_path, &args, &render_options)?,
+        };
+        let source_size = source_frame_size(video_path, args.decoder, args.backend)?;
+
+        Some(ExportInfo {
+            source_width: source_size.width,
+            source_height: source_size.height,
+            size_x,
+            size_y,
+            fps: 1000.0 / frame_delay as f64,
+            frame_delay,
+            charset: args.charset.clone(),
+            color: color_mode.to_possible_value().expect("No color mode is skipped").get_name().to_string(),
+            mode: mode.to_possible_value().expect("No mode is skipped").get_name().to_string(),
+            frame_count: frames_text.len(),
+        })
+    } else {
+        None
+    };
+
+    write_exports(&frames_text, frame_delay, &args, export_info.as_ref())?;
+
+    if exporting {
+        return Ok(());
+    }
 
-        frames_text.push(frame_text);
-        progress_bar.inc(1);
+    let audio_path = if video_paths.len() == 1 { Some(video_path) } else { None };
+
+    if args.audio && audio_path.is_none() && !args.quiet {
+        eprintln!("Warning: --audio only plays along with a single video");
     }
 
-    progress_bar.finish();
+    play(frames_text, frame_delay, &args, audio_path)
+}
+
+// --output, --html, --cast and --gif, export_info goes next to the files that have one
+fn write_exports(frames_text: &[String], frame_delay: u64, args: &Args, export_info: Option<&ExportInfo>) -> Result<()> {
+    if let Some(output_path) = &args.output {
+        write_frames(output_path, frames_text, &args.delimiter)?;
+
+        if let Some(export_info) = export_info {
+            write_export_info(output_path, export_info)?;
+        }
+
+        if !args.quiet {
+            println!("Wrote {} frames to {}", frames_text.len(), output_path);
+        }
+    }
 
+    if let Some(html_path) = &args.html {
+        write_html(html_path, frames_text, frame_delay)?;
 
-    println!("\nTime taken to get frames: {}ms", now.elapsed().as_millis());
-    same_line_print("Press enter to start animation ");
+        if !args.quiet {
+            println!("Wrote {} frames to {}", frames_text.len(), html_path);
+        }
+    }
 
-    std::io::stdin().read_line(&mut String::new()) // wait for input
-        .expect("Failed to read line");
+    if let Some(cast_path) = &args.cast {
+        write_cast(cast_path, frames_text, frame_delay)?;
 
-    
-    // for frame_text in frames_text.iter() {
-    //     print!("\x1B[2J\x1B[1;1H");
-    //     println!("{}", frame_text);
+        if let Some(export_info) = export_info {
+            write_export_info(cast_path, export_info)?;
+        }
 
-    //     std::thread::sleep(std::time::Duration::from_millis(FRAME_DELAY));
-    // }
-    print_frames(&frames_text, frame_delay)
-        .expect("Error while printing frames");
+        if !args.quiet {
+            println!("Wrote {} frames to {}", frames_text.len(), cast_path);
+        }
+    }
+
+    if let Some(gif_path) = &args.gif {
+        let font_path = find_font(args.font.as_deref())
+            .context("No monospace font found, pass one with --font")?;
+
+        write_gif(gif_path, frames_text, frame_delay, &font_path)?;
+
+        if !args.quiet {
+            println!("Wrote {} frames to {}", frames_text.len(), gif_path);
+        }
+    }
+
+    Ok(())
+}
+
+// Plays converted or replayed frames in the terminal, audio_path is the video whose sound plays along with --audio
+fn play(frames_text: Vec<String>, frame_delay: u64, args: &Args, audio_path: Option<&str>) -> Result<()> {
+    // repeated frames share one string for the rest of playback
+    let mut frames_text = intern_frames(frames_text);
+
+    // only the playback order changes, exports above stay forwards
+    if args.reverse {
+        frames_text.reverse();
+    }
+
+    // there and back, the frames it turns around on aren't shown twice
+    if args.bounce && frames_text.len() > 2 {
+        let back: Vec<_> = frames_text[1..frames_text.len() - 1].iter().rev().cloned().collect();
+        frames_text.extend(back);
+    }
+
+    if args.audio && (args.reverse || args.bounce) && !args.quiet {
+        eprintln!("Warning: --audio can't play backwards, playing without it");
+    }
+
+    let audio = match audio_path {
+        Some(audio_path) if args.audio && !args.reverse && !args.bounce => {
+            load_audio(audio_path, Trim { start: args.start, end: args.end, max_frames: None }, args.speed)
+        }
+        _ => None,
+    };
+
+    // with stdin piped or closed there's nobody to press enter and read_line would wait forever
+    if !args.quiet && !args.no_wait && std::io::stdin().is_terminal() {
+        same_line_print("Press enter to start animation ");
+
+        std::io::stdin().read_line(&mut String::new())?; // wait for input
+    }
+
+    let playback = playback_options(args, frame_delay)?;
+
+    handle_interrupts();
+
+    VideoToAscii::render(&frames_text, &playback, audio.as_ref())?;
+
+    Ok(())
+}
+
+fn playback_options(args: &Args, frame_delay: u64) -> Result<PlaybackOptions> {
+    // cues are timed from the start of the video, the frames from --start
+    let trimmed = (args.start.unwrap_or(0.0) * 1000.0) as u64;
+
+    let subtitles = match &args.subs {
+        Some(path) => load_subtitles(path)?
+            .into_iter()
+            .filter(|subtitle| subtitle.end > trimmed)
+            .map(|mut subtitle| {
+                subtitle.start = subtitle.start.saturating_sub(trimmed);
+                subtitle.end -= trimmed;
+                subtitle
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let plays = match args.loop_count {
+        Some(count) => Some(count),
+        None if args.looping || args.bounce => None,
+        None => Some(1),
+    };
+
+    Ok(PlaybackOptions {
+        frame_delay,
+        plays,
+        center: args.center,
+        diff: args.diff,
+        overlay: args.overlay,
+        inline: args.inline,
+        freeze: args.freeze,
+        subtitles,
+        source_delay: frame_delay as f64 * args.speed,
+        subtitle_background: args.subs_background,
+        stats: args.stats,
+        countdown: args.countdown,
+    })
+}
+
+// Converts on a background thread and starts playing as soon as the first frame is ready, no enter prompt.
+// Any loops after the first play run over the frames it collected, which also go into the cache
+fn play_in_background(
+    video_path: &str,
+    trim: Trim,
+    args: &Args,
+    render_options: &mut RenderOptions,
+    cache_path: Option<PathBuf>,
+) -> Result<()> {
+    let (size_x, size_y, letterbox, frame_delay) = clip_layout(video_path, args, render_options)?;
+    render_options.letterbox = letterbox;
+    check_width(size_x, letterbox, args)?;
+
+    let (frames, converter) = VideoToAscii::new(video_path)
+        .decoder(args.decoder)
+        .backend(args.backend)
+        .size(size_x, size_y)
+        .trim(trim)
+        .skip_every(args.skip_every)
+        .options(render_options.clone())
+        .spawn(BACKGROUND_FRAMES);
+
+    let playback = playback_options(args, frame_delay)?;
+    handle_interrupts();
+
+    let result = print_frames_streamed(&mut std::io::stdout(), frames, &playback);
+
+    // the receiver is gone by now, so the converter stops at its next frame
+    converter.join().expect("Converter thread panicked");
+    let (frames_text, finished) = result?;
+
+    if frames_text.is_empty() {
+        return Err(Failure::Undecodable(format!(
+            "No frames could be decoded from {}, it may be corrupt or use an unsupported codec",
+            video_path,
+        )).into());
+    }
+
+    if !finished {
+        return Ok(()); // quit partway, the frames don't cover the whole video so they aren't cached either
+    }
+
+    if let Some(cache_path) = &cache_path {
+        if let Err(e) = write_cache(cache_path, &frames_text, frame_delay) {
+            if !args.quiet {
+                eprintln!("Warning: Couldn't write the cache: {}", e);
+            }
+        }
+    }
+
+    let plays = playback.plays.map(|plays| plays.saturating_sub(1));
+    if plays == Some(0) {
+        return Ok(());
+    }
+
+    VideoToAscii::render(&intern_frames(frames_text), &PlaybackOptions { plays, ..playback }, None)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_cells_keep_the_cell_aspect() {
+        let args = Args::parse_from(["video-to-ascii", "-p", "unused.mp4", "--columns", "80", "--rows", "40", "--keep-aspect"]);
+        let square = Some(Size::new(100, 100));
+
+        // cells are twice as tall as wide, so 80 columns by 40 rows is square on screen
+        let half_blocks = RenderOptions { background: true, ..RenderOptions::default() };
+        assert_eq!(frame_layout(square, &args, &half_blocks), (80, 40, Some(Size::new(80, 40))));
+
+        let braille = RenderOptions { mode: Mode::Braille, ..RenderOptions::default() };
+        assert_eq!(frame_layout(square, &args, &braille), (80, 40, Some(Size::new(80, 40))));
+    }
 }